@@ -0,0 +1,267 @@
+//! Whole-tree atomic replacement
+//!
+//! The tree is serialized into a sibling staging directory (seeded with a copy of
+//! the current tree, so auxiliary files survive), and then swapped in with a pair
+//! of renames. The previous tree is kept as a backup until the swap succeeds.
+//!
+//! Copied files keep their permissions and modification times (so files left
+//! alone by `SerializerOptions::skip_unchanged` look untouched), but they are
+//! new files nevertheless. Directories keep their permissions, and entries
+//! other than files, directories and symbolic links (like FIFOs or sockets)
+//! are left out.
+//!
+//! Between the two renames, there is a short window in which nothing exists
+//! at the tree's path.
+
+use std::io;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ser::Error;
+
+fn sibling(path: &Path, suffix: &str) -> Result<PathBuf, io::Error> {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("{} has no file name", path.display()))),
+    };
+    let parent = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    Ok(parent.join(format!(".{}.serde_fs-{}", name, suffix)))
+}
+
+fn remove<P: AsRef<Path>>(path: P) -> Result<(), io::Error> {
+    let path = path.as_ref();
+    match fs::symlink_metadata(path) {
+        Ok(ref meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+// Symbolic links within the tree are copied as links (rather than followed),
+// so that the staging copy never pulls in anything from outside of the tree
+fn copy<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q, root: bool) -> Result<(), io::Error> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    let metadata = if root { fs::metadata(from)? } else { fs::symlink_metadata(from)? };
+    if metadata.file_type().is_symlink() {
+        copy_symlink(from, to)?;
+    } else if metadata.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy(entry.path(), to.join(entry.file_name()), false)?;
+        }
+        // Only once it's filled, as the permissions might not allow that
+        fs::set_permissions(to, metadata.permissions())?;
+    } else if metadata.is_file() {
+        let mut source = fs::File::open(from)?;
+        let mut target = fs::File::create(to)?;
+        io::copy(&mut source, &mut target)?;
+        target.set_modified(metadata.modified()?)?;
+        fs::set_permissions(to, metadata.permissions())?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> Result<(), io::Error> {
+    ::std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+// Creating symbolic links needs special privileges on Windows, so they are
+// left out of the staging copy
+#[cfg(not(unix))]
+fn copy_symlink(_from: &Path, _to: &Path) -> Result<(), io::Error> {
+    Ok(())
+}
+
+/// Runs `f` against a staging copy of `path` and atomically swaps the result in
+///
+/// If `f` fails, the staging directory is discarded and `path` is left untouched.
+pub(crate) fn replace<P: AsRef<Path>, F>(path: P, f: F) -> Result<(), Error>
+    where F: FnOnce(&Path) -> Result<(), Error> {
+    let path = path.as_ref();
+    let staging = sibling(path, "staging")?;
+    let backup = sibling(path, "backup")?;
+
+    // Recover from a previous run that was interrupted between the two renames
+    if fs::symlink_metadata(&backup).is_ok() {
+        if fs::symlink_metadata(path).is_err() {
+            fs::rename(&backup, path)?;
+        } else {
+            remove(&backup)?;
+        }
+    }
+    remove(&staging)?;
+
+    let exists = fs::symlink_metadata(path).is_ok();
+    if exists {
+        copy(path, &staging, true)?;
+    }
+
+    if let Err(err) = f(&staging) {
+        remove(&staging)?;
+        return Err(err);
+    }
+
+    if exists {
+        fs::rename(path, &backup)?;
+        if let Err(err) = fs::rename(&staging, path) {
+            fs::rename(&backup, path)?;
+            remove(&staging)?;
+            return Err(err.into());
+        }
+        remove(&backup)?;
+    } else {
+        fs::rename(&staging, path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+    use std::io::Read;
+    use std::path::Path;
+
+    use serde::{Serialize, Serializer};
+    use serde::ser::{SerializeStruct, Error as SerdeError};
+    use tempdir::TempDir;
+
+    use super::super::{to_fs, to_fs_atomic, to_fs_with, remove_with, SerializerOptions};
+
+    fn file_to_string<P: AsRef<Path>>(path: P) -> String {
+        let mut file = fs::File::open(path).unwrap();
+        let mut s = String::new();
+        file.read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[derive(Serialize)]
+    struct Struct {
+        test: u8,
+        passed: u64,
+    }
+
+    struct Failing;
+
+    impl Serialize for Failing {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut s = serializer.serialize_struct("Failing", 2)?;
+            s.serialize_field("test", &1u8)?;
+            Err(S::Error::custom("failed halfway"))
+        }
+    }
+
+    #[test]
+    fn replace() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        to_fs(&path, &Struct { test: 100, passed: 2100 }).unwrap();
+        fs::write(path.join("README.md"), "Hello!").unwrap();
+        to_fs_atomic(&path, &Struct { test: 1, passed: 2 }).unwrap();
+        assert_eq!(file_to_string(path.join("test")), "1");
+        assert_eq!(file_to_string(path.join("passed")), "2");
+        assert_eq!(file_to_string(path.join("README.md")), "Hello!");
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn create() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        to_fs_atomic(&path, &Struct { test: 1, passed: 2 }).unwrap();
+        assert_eq!(file_to_string(path.join("test")), "1");
+        assert_eq!(file_to_string(path.join("passed")), "2");
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn failure_keeps_old_tree() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        to_fs(&path, &Struct { test: 100, passed: 2100 }).unwrap();
+        assert!(to_fs_atomic(&path, &Failing).is_err());
+        assert_eq!(file_to_string(path.join("test")), "100");
+        assert_eq!(file_to_string(path.join("passed")), "2100");
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_not_followed() {
+        use std::os::unix::fs::symlink;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        fs::create_dir_all(tmp.path().join("outside")).unwrap();
+        fs::write(tmp.path().join("outside").join("secret"), "secret").unwrap();
+        to_fs(&path, &Struct { test: 100, passed: 2100 }).unwrap();
+        symlink(tmp.path().join("outside"), path.join("link")).unwrap();
+        to_fs_atomic(&path, &Struct { test: 1, passed: 2 }).unwrap();
+        assert!(fs::symlink_metadata(path.join("link")).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(path.join("link")).unwrap(), tmp.path().join("outside"));
+        assert_eq!(file_to_string(path.join("test")), "1");
+    }
+
+    #[test]
+    fn skip_unchanged() {
+        use std::time::{Duration, SystemTime};
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        let options = SerializerOptions::new().atomic(true).skip_unchanged(true);
+        to_fs_with(&path, &Struct { test: 100, passed: 2100 }, &options).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options().write(true).open(path.join("test")).unwrap().set_modified(modified).unwrap();
+        to_fs_with(&path, &Struct { test: 100, passed: 2 }, &options).unwrap();
+        assert_eq!(fs::metadata(path.join("test")).unwrap().modified().unwrap(), modified);
+        assert_eq!(file_to_string(path.join("passed")), "2");
+    }
+
+    #[test]
+    fn remove_shifts_atomically() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("seq");
+        to_fs(&path, &vec![1, 2, 3]).unwrap();
+        remove_with(&path, "0", &SerializerOptions::new().atomic(true)).unwrap();
+        assert_eq!(file_to_string(path.join("0")), "2");
+        assert_eq!(file_to_string(path.join("1")), "3");
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 2);
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn special_files_and_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::process::Command;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        to_fs(&path, &Struct { test: 100, passed: 2100 }).unwrap();
+        fs::create_dir(path.join("private")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o700)).unwrap();
+        fs::set_permissions(path.join("private"), fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(Command::new("mkfifo").arg(path.join("fifo")).status().unwrap().success());
+        to_fs_atomic(&path, &Struct { test: 1, passed: 2 }).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o700);
+        assert_eq!(mode(&path.join("private")), 0o700);
+        assert!(!path.join("fifo").exists());
+        assert_eq!(file_to_string(path.join("test")), "1");
+    }
+
+    #[test]
+    fn recover_interrupted_swap() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        to_fs(tmp.path().join(".struct.serde_fs-backup"), &Struct { test: 100, passed: 2100 }).unwrap();
+        to_fs_atomic(&path, &Struct { test: 1, passed: 2 }).unwrap();
+        assert_eq!(file_to_string(path.join("test")), "1");
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+}
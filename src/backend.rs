@@ -0,0 +1,250 @@
+//! Storage trees are serialized to and deserialized from
+
+use std::io::{self, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ser::{SerializerOptions, Durability};
+use confine;
+
+/// Kind of an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Dir,
+}
+
+/// Storage of trees
+///
+/// Paths handed to a backend are the serializer's (or deserializer's) path
+/// joined with file names of the tree's entries.
+pub trait Backend: Send + Sync {
+    /// Kind of the entry at `path` (following symbolic links), if there is one
+    fn kind(&self, path: &Path) -> Result<Option<Kind>, io::Error>;
+
+    /// Checks whether there is anything at `path`, including a dangling symbolic link
+    fn exists(&self, path: &Path) -> Result<bool, io::Error> {
+        Ok(self.kind(path)?.is_some())
+    }
+
+    /// Names of a directory's entries
+    fn read_dir(&self, path: &Path) -> Result<Vec<String>, io::Error>;
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, io::Error>;
+
+    /// Size of a file in bytes
+    fn size(&self, path: &Path) -> Result<u64, io::Error> {
+        Ok(self.read(path)?.len() as u64)
+    }
+
+    /// Creates a directory along with its missing parents
+    fn create_dir(&self, path: &Path) -> Result<(), io::Error>;
+
+    /// Creates or overwrites a file
+    fn write(&self, path: &Path, content: &[u8]) -> Result<(), io::Error>;
+
+    /// Removes a file or a whole directory (symbolic links are not followed)
+    fn remove(&self, path: &Path) -> Result<(), io::Error>;
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), io::Error>;
+
+    /// Applies the permissions new files or directories get to an existing
+    /// one left alone otherwise (backends without permissions do nothing)
+    fn set_permissions(&self, _path: &Path) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    /// Resolves symbolic links in `path`, so that access through them can be
+    /// confined to the root (backends without symbolic links return `path`)
+    ///
+    /// Returns `None` if `path` is a dangling symbolic link.
+    fn resolve(&self, path: &Path) -> Result<Option<PathBuf>, io::Error> {
+        Ok(Some(path.to_path_buf()))
+    }
+}
+
+/// Visits `path` and everything in it, directories before their entries
+/// and entries in the order of their names, passing the contents of files
+#[cfg(any(feature = "tar", feature = "zip"))]
+pub(crate) fn walk<F>(backend: &dyn Backend, path: &Path, f: &mut F) -> Result<(), io::Error>
+    where F: FnMut(&Path, Option<Vec<u8>>) -> Result<(), io::Error> {
+    match backend.kind(path)? {
+        None => Ok(()),
+        Some(Kind::File) => f(path, Some(backend.read(path)?)),
+        Some(Kind::Dir) => {
+            f(path, None)?;
+            let mut names = backend.read_dir(path)?;
+            names.sort();
+            for name in names {
+                walk(backend, &path.join(name), f)?;
+            }
+            Ok(())
+        },
+    }
+}
+
+/// The local file system (default)
+#[derive(Debug, Clone, Default)]
+pub struct StdFs {
+    durability: Durability,
+    file_mode: Option<u32>,
+    dir_mode: Option<u32>,
+}
+
+impl StdFs {
+    /// Local file system writing files according to given options'
+    /// durability policy and permissions
+    pub fn new(options: &SerializerOptions) -> Self {
+        StdFs {
+            durability: options.durability,
+            file_mode: options.file_mode,
+            dir_mode: options.dir_mode,
+        }
+    }
+}
+
+impl Backend for StdFs {
+    fn kind(&self, path: &Path) -> Result<Option<Kind>, io::Error> {
+        match fs::metadata(path) {
+            Ok(ref metadata) if metadata.is_dir() => Ok(Some(Kind::Dir)),
+            Ok(_) => Ok(Some(Kind::File)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, io::Error> {
+        Ok(fs::symlink_metadata(path).is_ok())
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<String>, io::Error> {
+        let mut names = vec![];
+        for entry in fs::read_dir(path)? {
+            names.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        Ok(names)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, io::Error> {
+        fs::read(path)
+    }
+
+    fn size(&self, path: &Path) -> Result<u64, io::Error> {
+        Ok(fs::metadata(path)?.len())
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), io::Error> {
+        if path.is_dir() {
+            return Ok(());
+        }
+        // Parents are created one by one, so that each of them gets `dir_mode`
+        match path.parent() {
+            Some(parent) if parent != Path::new("") => self.create_dir(parent)?,
+            _ => (),
+        }
+        match fs::create_dir(path) {
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists && path.is_dir() => Ok(()),
+            Err(err) => Err(err),
+            Ok(()) => set_mode(path, self.dir_mode),
+        }
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<(), io::Error> {
+        if self.durability == Durability::None {
+            let mut file = fs::File::create(path)?;
+            file.write_all(content)?;
+            return set_mode(path, self.file_mode);
+        }
+
+        let dir = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let tmp = TempFile(dir.join(format!(".{}.serde_fs-tmp", name)), false);
+        {
+            let mut file = fs::File::create(&tmp.0)?;
+            file.write_all(content)?;
+            match fs::metadata(path) {
+                // The replacement keeps the permissions of the file it replaces
+                Ok(ref metadata) if self.file_mode.is_none() => fs::set_permissions(&tmp.0, metadata.permissions())?,
+                _ => set_mode(&tmp.0, self.file_mode)?,
+            }
+            if self.durability == Durability::Sync {
+                file.sync_all()?;
+            }
+        }
+        fs::rename(&tmp.0, path)?;
+        tmp.persist();
+        if self.durability == Durability::Sync {
+            sync_dir(&dir)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), io::Error> {
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), io::Error> {
+        fs::rename(from, to)
+    }
+
+    fn set_permissions(&self, path: &Path) -> Result<(), io::Error> {
+        if fs::metadata(path)?.is_dir() {
+            set_mode(path, self.dir_mode)
+        } else {
+            set_mode(path, self.file_mode)
+        }
+    }
+
+    fn resolve(&self, path: &Path) -> Result<Option<PathBuf>, io::Error> {
+        confine::resolve(path)
+    }
+}
+
+#[cfg(unix)]
+/// Temporary file that is removed unless it was renamed into place
+struct TempFile(PathBuf, bool);
+
+impl TempFile {
+    fn persist(mut self) {
+        self.1 = true;
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.1 {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+}
+
+fn set_mode<P: AsRef<Path>>(path: P, mode: Option<u32>) -> Result<(), io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    match mode {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_mode<P: AsRef<Path>>(_path: P, _mode: Option<u32>) -> Result<(), io::Error> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn sync_dir<P: AsRef<Path>>(path: P) -> Result<(), io::Error> {
+    fs::File::open(path)?.sync_all()
+}
+
+// Directories can't be opened (and thus fsynced) as files on other platforms
+#[cfg(not(unix))]
+fn sync_dir<P: AsRef<Path>>(_path: P) -> Result<(), io::Error> {
+    Ok(())
+}
@@ -0,0 +1,387 @@
+//! Command-line access to serde_fs trees
+//!
+//! Usage: `serde-fs [--percent-encoding] <command> <root> [<pointer>] ...`,
+//! see `USAGE` for the commands.
+
+extern crate serde_fs;
+#[cfg(test)] extern crate tempdir;
+#[cfg(test)] #[macro_use] extern crate assert_matches;
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+use std::collections::BTreeMap;
+
+use serde_fs::pointer;
+use serde_fs::{Value, SerializerOptions, DeserializerOptions, SerializerError, DeserializerError,
+               PercentEncoding, Prune};
+
+const USAGE: &str = "\
+usage: serde-fs [--percent-encoding] <command> <root> [<pointer>] ...
+
+commands:
+  get <root> <pointer>                  print the contents of a file
+  set <root> <pointer> <value>          write a file
+  set <root> <pointer> --variant <name> [<value> | --field <key>=<value> ...]
+                                        switch an enum to a unit, newtype,
+                                        or struct (tuple) variant
+  rm <root> <pointer>                   remove a map key or a sequence element
+                                        (shifting the elements following it)
+  ls <root> [<pointer>]                 list keys of a map (as pointer segments)
+                                        or indices of a sequence
+  tree <root> [<pointer>]               print a subtree
+
+Pointers are `/`-separated keys and indices (like `servers/0/port`), with
+`~1` standing for `/` and `~0` for `~` in keys.";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Get { root: String, pointer: String },
+    Set { root: String, pointer: String, value: Value },
+    Remove { root: String, pointer: String },
+    List { root: String, pointer: String },
+    Tree { root: String, pointer: String },
+}
+
+#[derive(Debug, PartialEq)]
+enum Error {
+    Usage(String),
+    Failed(String),
+}
+
+impl From<SerializerError> for Error {
+    fn from(err: SerializerError) -> Self {
+        Error::Failed(match err {
+            SerializerError::MissingSegment(pointer) => format!("nothing at `{}`", pointer),
+            SerializerError::InvalidPointer(pointer) => format!("invalid pointer `{}`", pointer),
+            SerializerError::InvalidIndex(pointer) => format!("`{}` is past the end of the sequence", pointer),
+            SerializerError::PathTraversal(path) => format!("`{}` is outside of the root", path.display()),
+            SerializerError::IoError(err) => err.to_string(),
+            err => format!("{:?}", err),
+        })
+    }
+}
+
+impl From<DeserializerError> for Error {
+    fn from(err: DeserializerError) -> Self {
+        Error::Failed(match err {
+            DeserializerError::MissingSegment(pointer) => format!("nothing at `{}`", pointer),
+            DeserializerError::InvalidPointer(pointer) => format!("invalid pointer `{}`", pointer),
+            DeserializerError::PathTraversal(path) => format!("`{}` is outside of the root", path.display()),
+            DeserializerError::IoError(err) => err.to_string(),
+            err => format!("{:?}", err),
+        })
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Failed(err.to_string())
+    }
+}
+
+struct Options {
+    percent_encoding: bool,
+}
+
+impl Options {
+    fn serializer(&self) -> SerializerOptions {
+        // Subtrees are replaced as a whole, so that fields of a previous
+        // enum variant don't linger
+        let options = SerializerOptions::new().prune(Prune::Exact(vec![]));
+        if self.percent_encoding { options.key_codec(PercentEncoding) } else { options }
+    }
+
+    // Files are read as they are: without type inference (which would turn
+    // `080` into `80`), trimming or stripping trailing newlines
+    fn deserializer(&self) -> DeserializerOptions {
+        let options = DeserializerOptions::new().infer_types(false).trim(false).trailing_newline(false);
+        if self.percent_encoding { options.key_codec(PercentEncoding) } else { options }
+    }
+}
+
+fn usage<T>(message: &str) -> Result<T, Error> {
+    Err(Error::Usage(message.into()))
+}
+
+fn parse<I: Iterator<Item = String>>(args: I) -> Result<(Options, Command), Error> {
+    let mut options = Options { percent_encoding: false };
+    let mut args = args.peekable();
+    while args.peek().is_some_and(|arg| arg.starts_with("--")) {
+        match args.next().unwrap().as_str() {
+            "--percent-encoding" => options.percent_encoding = true,
+            other => return usage(&format!("unknown option `{}`", other)),
+        }
+    }
+    let command = match args.next() {
+        Some(command) => command,
+        None => return usage("missing command"),
+    };
+    let root = match args.next() {
+        Some(root) => root,
+        None => return usage("missing root"),
+    };
+    let pointer = args.next();
+    let command = match (command.as_str(), pointer) {
+        ("get", Some(pointer)) => Command::Get { root, pointer },
+        ("set", Some(ref pointer)) if pointer.is_empty() => return usage("refusing to replace the root"),
+        ("set", Some(pointer)) => {
+            let value = parse_value(&mut args)?;
+            Command::Set { root, pointer, value }
+        },
+        ("rm", Some(ref pointer)) if pointer.is_empty() => return usage("refusing to remove the root"),
+        ("rm", Some(pointer)) => Command::Remove { root, pointer },
+        ("ls", pointer) => Command::List { root, pointer: pointer.unwrap_or_default() },
+        ("tree", pointer) => Command::Tree { root, pointer: pointer.unwrap_or_default() },
+        ("get", None) | ("set", None) | ("rm", None) => return usage("missing pointer"),
+        (other, _) => return usage(&format!("unknown command `{}`", other)),
+    };
+    if let Some(arg) = args.next() {
+        return usage(&format!("unexpected argument `{}`", arg));
+    }
+    Ok((options, command))
+}
+
+/// Value of `set`, following the mapping of enum variants
+fn parse_value<I: Iterator<Item = String>>(args: &mut I) -> Result<Value, Error> {
+    let first = match args.next() {
+        Some(first) => first,
+        None => return usage("missing value"),
+    };
+    if first != "--variant" {
+        return Ok(Value::from(first));
+    }
+    let variant = match args.next() {
+        Some(variant) => variant,
+        None => return usage("missing variant name"),
+    };
+    let mut fields = BTreeMap::new();
+    let mut value = None;
+    while let Some(arg) = args.next() {
+        if arg == "--field" {
+            let field = match args.next() {
+                Some(field) => field,
+                None => return usage("missing field"),
+            };
+            match field.find('=') {
+                Some(index) => fields.insert(field[..index].to_string(), Value::from(&field[index + 1..])),
+                None => return usage(&format!("field `{}` is not <key>=<value>", field)),
+            };
+        } else if value.is_none() && fields.is_empty() {
+            value = Some(arg);
+        } else {
+            return usage(&format!("unexpected argument `{}`", arg));
+        }
+    }
+    match value {
+        Some(_) if !fields.is_empty() => usage("a variant has either a value or fields"),
+        Some(value) => {
+            fields.insert("value".into(), Value::from(value));
+            fields.insert("variant".into(), Value::from(variant));
+            Ok(Value::Dir(fields))
+        },
+        None if fields.is_empty() => Ok(Value::from(variant)),
+        None if fields.contains_key("variant") => usage("`variant` can't be a field of a variant"),
+        None => {
+            fields.insert("variant".into(), Value::from(variant));
+            Ok(Value::Dir(fields))
+        },
+    }
+}
+
+fn run<W: Write>(options: &Options, command: Command, out: &mut W) -> Result<(), Error> {
+    match command {
+        Command::Get { root, pointer } => {
+            match serde_fs::get_with(&root, &pointer, &options.deserializer())? {
+                Value::File(bytes) => out.write_all(&bytes)?,
+                Value::Missing => return Err(Error::Failed(format!("nothing at `{}`", pointer))),
+                _ => return Err(Error::Failed(format!("`{}` is a directory (see ls and tree)", pointer))),
+            }
+        },
+        Command::Set { root, pointer, value } => {
+            serde_fs::set_with(&root, &pointer, &value, &options.serializer())?;
+        },
+        Command::Remove { root, pointer } => {
+            serde_fs::remove_with(&root, &pointer, &options.serializer())?;
+        },
+        Command::List { root, pointer } => {
+            match serde_fs::get_with(&root, &pointer, &options.deserializer())? {
+                Value::Dir(map) => for (key, value) in map {
+                    writeln!(out, "{}{}", pointer::escape(&key), if is_dir(&value) { "/" } else { "" })?;
+                },
+                Value::Seq(seq) => for (index, value) in seq.iter().enumerate() {
+                    writeln!(out, "{}{}", index, if is_dir(value) { "/" } else { "" })?;
+                },
+                Value::Missing => return Err(Error::Failed(format!("nothing at `{}`", pointer))),
+                Value::File(_) => return Err(Error::Failed(format!("`{}` is a file (see get)", pointer))),
+            }
+        },
+        Command::Tree { root, pointer } => {
+            let value: Value = serde_fs::get_with(&root, &pointer, &options.deserializer())?;
+            if value.is_missing() {
+                return Err(Error::Failed(format!("nothing at `{}`", pointer)));
+            }
+            let name = if pointer.is_empty() { root } else { pointer };
+            render(out, &name, &value, 0)?;
+        },
+    }
+    Ok(())
+}
+
+fn is_dir(value: &Value) -> bool {
+    matches!(*value, Value::Dir(_) | Value::Seq(_))
+}
+
+/// Renders a subtree, one entry per line
+///
+/// Sequences are annotated with their length, and enums with their variant
+/// (hiding the `variant` file).
+fn render<W: Write>(out: &mut W, name: &str, value: &Value, depth: usize) -> Result<(), io::Error> {
+    let indent = "  ".repeat(depth);
+    match *value {
+        Value::Missing => Ok(()),
+        Value::File(ref bytes) => writeln!(out, "{}{}: {}", indent, name, summary(bytes)),
+        Value::Seq(ref seq) => {
+            writeln!(out, "{}{}/ [{}]", indent, name, seq.len())?;
+            for (index, value) in seq.iter().enumerate() {
+                render(out, &index.to_string(), value, depth + 1)?;
+            }
+            Ok(())
+        },
+        Value::Dir(ref map) => {
+            let variant = map.get("variant").and_then(Value::as_str);
+            match variant {
+                Some(variant) => writeln!(out, "{}{}/ ({})", indent, name, variant.trim_end())?,
+                None => writeln!(out, "{}{}/", indent, name)?,
+            }
+            for (key, value) in map {
+                if variant.is_none() || key != "variant" {
+                    render(out, key, value, depth + 1)?;
+                }
+            }
+            Ok(())
+        },
+    }
+}
+
+/// Contents of a file as they fit on a single line
+fn summary(bytes: &[u8]) -> String {
+    match ::std::str::from_utf8(bytes) {
+        Ok(s) if !s.trim_end_matches('\n').contains('\n') => s.trim_end_matches('\n').to_string(),
+        Ok(s) => format!("<{} lines>", s.lines().count()),
+        Err(_) => format!("<{} bytes>", bytes.len()),
+    }
+}
+
+fn main() {
+    let stdout = io::stdout();
+    let result = parse(env::args().skip(1))
+        .and_then(|(options, command)| run(&options, command, &mut stdout.lock()));
+    match result {
+        Ok(()) => (),
+        Err(Error::Usage(message)) => {
+            eprintln!("serde-fs: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        },
+        Err(Error::Failed(message)) => {
+            eprintln!("serde-fs: {}", message);
+            process::exit(1);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn args(args: &str) -> Result<Command, Error> {
+        parse(args.split(' ').map(String::from)).map(|(_, command)| command)
+    }
+
+    fn exec(root: &TempDir, line: &str) -> Result<String, Error> {
+        let line = line.replacen("ROOT", root.path().to_str().unwrap(), 1);
+        let (options, command) = parse(line.split(' ').map(String::from))?;
+        let mut out = vec![];
+        run(&options, command, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(args("get root servers/0").unwrap(), Command::Get { root: "root".into(), pointer: "servers/0".into() });
+        assert_eq!(args("ls root").unwrap(), Command::List { root: "root".into(), pointer: "".into() });
+        assert_eq!(args("set root mode --variant Plain").unwrap(),
+                   Command::Set { root: "root".into(), pointer: "mode".into(), value: Value::from("Plain") });
+        match args("set root mode --variant Tls --field cert=a=b").unwrap() {
+            Command::Set { value, .. } => {
+                assert_eq!(value["variant"], Value::from("Tls"));
+                assert_eq!(value["cert"], Value::from("a=b"));
+            },
+            command => panic!("unexpected command {:?}", command),
+        }
+        assert_matches!(args("rm root "), Err(Error::Usage(_)));
+        assert_matches!(args("set root  1"), Err(Error::Usage(_)));
+        assert_matches!(args("rm root"), Err(Error::Usage(_)));
+        assert_matches!(args("get root a b"), Err(Error::Usage(_)));
+        assert_matches!(args("set root mode --variant Tls value --field cert=x"), Err(Error::Usage(_)));
+        assert_matches!(args("mv root a"), Err(Error::Usage(_)));
+        assert_matches!(args("--verbose ls root"), Err(Error::Usage(_)));
+    }
+
+    #[test]
+    fn commands() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        exec(&tmp, "set ROOT servers/0/host localhost").unwrap();
+        exec(&tmp, "set ROOT servers/1/host example.com").unwrap();
+        exec(&tmp, "set ROOT servers/1/mode --variant Plain").unwrap();
+        assert_eq!(exec(&tmp, "get ROOT servers/1/mode").unwrap(), "Plain");
+        assert_eq!(exec(&tmp, "ls ROOT servers").unwrap(), "0/\n1/\n");
+        assert_eq!(exec(&tmp, "ls ROOT servers/1").unwrap(), "host\nmode\n");
+
+        exec(&tmp, "set ROOT servers/1/mode --variant Tls --field cert=cert.pem").unwrap();
+        assert_eq!(fs::read_to_string(tmp.path().join("servers/1/mode/variant")).unwrap(), "Tls");
+        exec(&tmp, "set ROOT servers/1/mode --variant Custom custom.conf").unwrap();
+        assert_eq!(exec(&tmp, "ls ROOT servers/1/mode").unwrap(), "value\nvariant\n");
+        assert_matches!(exec(&tmp, "get ROOT servers/1/mode"), Err(Error::Failed(_)));
+
+        assert_eq!(exec(&tmp, "tree ROOT servers").unwrap(),
+                   "servers/ [2]\n  0/\n    host: localhost\n  1/\n    host: example.com\n    mode/ (Custom)\n      value: custom.conf\n");
+
+        exec(&tmp, "rm ROOT servers/0").unwrap();
+        assert_eq!(exec(&tmp, "get ROOT servers/0/host").unwrap(), "example.com");
+        assert_eq!(exec(&tmp, "rm ROOT servers/1"), Err(Error::Failed("nothing at `servers/1`".into())));
+        assert_matches!(exec(&tmp, "get ROOT ../etc"), Err(Error::Failed(_)));
+    }
+
+    #[test]
+    fn raw_contents() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::create_dir_all(tmp.path().join("server")).unwrap();
+        let files = [("port", "080\n"), ("ratio", "1.50"), ("enabled", " true "), ("offset", "+5"), ("empty", "")];
+        for &(name, content) in &files {
+            fs::write(tmp.path().join("server").join(name), content).unwrap();
+            assert_eq!(exec(&tmp, &format!("get ROOT server/{}", name)).unwrap(), content);
+        }
+        assert_eq!(exec(&tmp, "tree ROOT server").unwrap(),
+                   "server/\n  empty: \n  enabled:  true \n  offset: +5\n  port: 080\n  ratio: 1.50\n");
+    }
+
+    #[test]
+    fn percent_encoding() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        exec(&tmp, "--percent-encoding set ROOT hosts/a~1b 1").unwrap();
+        assert!(tmp.path().join("hosts/a%2Fb").is_file());
+        assert_eq!(exec(&tmp, "--percent-encoding ls ROOT hosts").unwrap(), "a~1b\n");
+    }
+
+    #[test]
+    fn summaries() {
+        assert_eq!(summary(b"80\n"), "80");
+        assert_eq!(summary(b"a\nb\n"), "<2 lines>");
+        assert_eq!(summary(&[0xff, 0xfe]), "<2 bytes>");
+    }
+
+}
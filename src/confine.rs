@@ -0,0 +1,136 @@
+//! Confinement of file system access to a root directory
+
+use std::io;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use backend::Backend;
+
+/// Resolves symbolic links in the longest existing prefix of `path`
+///
+/// Returns `None` if `path` ends with a dangling symbolic link, as there's no
+/// telling where it will lead once written to.
+pub(crate) fn resolve(path: &Path) -> Result<Option<PathBuf>, io::Error> {
+    let mut existing = path;
+    let mut rest = vec![];
+    loop {
+        match fs::canonicalize(existing) {
+            Ok(mut resolved) => {
+                for name in rest.iter().rev() {
+                    resolved.push(name);
+                }
+                return Ok(Some(resolved));
+            },
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                if fs::symlink_metadata(existing).is_ok() {
+                    return Ok(None);
+                }
+                match (existing.parent(), existing.file_name()) {
+                    (Some(parent), Some(name)) => {
+                        rest.push(name);
+                        existing = if parent == Path::new("") { Path::new(".") } else { parent };
+                    },
+                    _ => return Ok(Some(path.to_path_buf())),
+                }
+            },
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Root directory that all file system access has to stay within
+#[derive(Debug)]
+pub(crate) struct Root {
+    path: PathBuf,
+}
+
+impl Root {
+    pub fn new<P: AsRef<Path>>(path: P, backend: &dyn Backend) -> Self {
+        let path = path.as_ref();
+        let resolved = match backend.resolve(path) {
+            Ok(Some(resolved)) => resolved,
+            _ => path.to_path_buf(),
+        };
+        Root { path: resolved }
+    }
+
+    /// Checks whether `path` (with symbolic links resolved) is within the root
+    pub fn contains<P: AsRef<Path>>(&self, path: P, backend: &dyn Backend) -> Result<bool, io::Error> {
+        Ok(match backend.resolve(path.as_ref())? {
+            Some(resolved) => resolved.starts_with(&self.path),
+            None => false,
+        })
+    }
+}
+
+/// Checks whether `name` can be safely joined to a directory path
+/// (it names a single entry, and is not `.` or `..`)
+pub(crate) fn is_local_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(component)), None) => component == name,
+        _ => false,
+    }
+}
+
+/// Checks whether a struct field's `name` can be safely joined to a directory path
+///
+/// Unlike map keys, field names come from the type rather than the data, so
+/// they may also be `.`, placing the field in the struct's own directory.
+pub(crate) fn is_field_name(name: &str) -> bool {
+    name == "." || is_local_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tempdir::TempDir;
+    use backend::StdFs;
+
+    #[test]
+    fn local_names() {
+        assert!(is_local_name("test"));
+        assert!(is_local_name(".hidden"));
+        assert!(!is_local_name("."));
+        assert!(!is_local_name("a/b"));
+        assert!(!is_local_name("./a"));
+        assert!(!is_local_name("a/"));
+        assert!(!is_local_name(""));
+        assert!(!is_local_name(".."));
+        assert!(!is_local_name("/a"));
+        assert!(!is_local_name("../a"));
+        assert!(!is_local_name("a/../../b"));
+        assert!(is_field_name("."));
+        assert!(is_field_name("test"));
+        assert!(!is_field_name(".."));
+        assert!(!is_field_name("a/b"));
+    }
+
+    #[test]
+    fn contains() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let root = Root::new(tmp.path().join("root"), &StdFs::default());
+        assert!(root.contains(tmp.path().join("root"), &StdFs::default()).unwrap());
+        assert!(root.contains(tmp.path().join("root").join("a").join("b"), &StdFs::default()).unwrap());
+        assert!(!root.contains(tmp.path().join("other"), &StdFs::default()).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn contains_symlinks() {
+        use std::os::unix::fs::symlink;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::create_dir_all(tmp.path().join("root").join("dir")).unwrap();
+        fs::create_dir_all(tmp.path().join("other")).unwrap();
+        symlink(tmp.path().join("other"), tmp.path().join("root").join("out")).unwrap();
+        symlink(tmp.path().join("root").join("dir"), tmp.path().join("root").join("in")).unwrap();
+        symlink(tmp.path().join("missing"), tmp.path().join("root").join("dangling")).unwrap();
+        let root = Root::new(tmp.path().join("root"), &StdFs::default());
+        assert!(root.contains(tmp.path().join("root").join("in").join("file"), &StdFs::default()).unwrap());
+        assert!(!root.contains(tmp.path().join("root").join("out"), &StdFs::default()).unwrap());
+        assert!(!root.contains(tmp.path().join("root").join("out").join("file"), &StdFs::default()).unwrap());
+        assert!(!root.contains(tmp.path().join("root").join("dangling"), &StdFs::default()).unwrap());
+    }
+
+}
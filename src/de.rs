@@ -1,11 +1,18 @@
-use std::io::{self, Read};
-use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
 
 use serde::Deserializer;
 use serde::de::{self, Visitor, Error as SerdeError};
 
+use key::{KeyCodec, Verbatim};
+use env::EnvOverrides;
+use confine::{Root, is_local_name, is_field_name};
+use backend::{Backend, Kind, StdFs};
+use pointer;
+
 #[derive(Debug, Error)]
 pub enum Error {
     IoError(io::Error),
@@ -23,6 +30,15 @@ pub enum Error {
     #[error(non_std, no_from)]
     InvalidEnum(String),
     #[error(non_std, no_from)]
+    InvalidKey(String),
+    #[error(non_std, no_from)]
+    PathTraversal(PathBuf),
+    #[error(non_std, no_from)]
+    InvalidPointer(String),
+    /// Pointer up to (and including) the first segment that doesn't exist
+    #[error(non_std, no_from)]
+    MissingSegment(String),
+    #[error(non_std, no_from)]
     Custom(String),
 }
 
@@ -32,137 +48,387 @@ impl SerdeError for Error {
     }
 }
 
+/// Merging policy for sequences present in several layers (see `from_fs_layers`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeqLayering {
+    /// Merge sequences element by element, so that a layer can override a
+    /// single element (like `servers/0/port`) or remove one with a deletion
+    /// marker (like `servers/.deleted.0`, shifting the elements following it)
+    #[default]
+    Merge,
+    /// Take the sequence from the highest-priority layer that has it
+    Replace,
+    /// Concatenate sequences from all layers, lowest-priority layer first
+    Append,
+}
+
+/// Deserializer configuration
+///
+/// ```
+/// use serde_fs::DeserializerOptions;
+///
+/// let options = DeserializerOptions::new()
+///     .trailing_newline(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct DeserializerOptions {
+    trim: bool,
+    infer_types: bool,
+    trailing_newline: bool,
+    key_codec: Arc<dyn KeyCodec>,
+    pub(crate) seq_layering: SeqLayering,
+    pub(crate) deletion_marker: String,
+    env_overrides: Option<EnvOverrides>,
+}
+
+impl Default for DeserializerOptions {
+    fn default() -> Self {
+        DeserializerOptions {
+            trim: false,
+            infer_types: true,
+            trailing_newline: false,
+            key_codec: Arc::new(Verbatim),
+            seq_layering: SeqLayering::default(),
+            deletion_marker: String::from(".deleted."),
+            env_overrides: None,
+        }
+    }
+}
+
+impl DeserializerOptions {
+    pub fn new() -> Self {
+        DeserializerOptions::default()
+    }
+
+    /// Trim leading and trailing whitespace of strings, variant names and identifiers
+    ///
+    /// Numbers and booleans are always trimmed.
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Let self-describing deserialization (`deserialize_any`) see leaf files
+    /// as booleans, numbers or unit (when empty) instead of strings (on by default)
+    ///
+    /// This is what internally tagged, adjacently tagged and untagged enums, as
+    /// well as flattened structs, need to read non-string fields. Only canonical
+    /// forms are recognized (`80`, but not `080`, `+80` or ` 80`), yet string fields
+    /// holding such values can't be read through them unless this is turned off.
+    pub fn infer_types(mut self, infer_types: bool) -> Self {
+        self.infer_types = infer_types;
+        self
+    }
+
+    /// Strip a single trailing newline from strings
+    /// (see `SerializerOptions::trailing_newline`)
+    pub fn trailing_newline(mut self, trailing_newline: bool) -> Self {
+        self.trailing_newline = trailing_newline;
+        self
+    }
+
+    /// Mapping of map and struct keys to file names (`Verbatim` by default)
+    /// (see `SerializerOptions::key_codec`)
+    pub fn key_codec<K: KeyCodec + 'static>(mut self, key_codec: K) -> Self {
+        self.key_codec = Arc::new(key_codec);
+        self
+    }
+
+    /// Merging policy for sequences when reading layers (`SeqLayering::Merge` by default)
+    pub fn seq_layering(mut self, seq_layering: SeqLayering) -> Self {
+        self.seq_layering = seq_layering;
+        self
+    }
+
+    /// Prefix of file names marking deletions when reading layers
+    /// (`.deleted.` by default)
+    ///
+    /// A layer containing `.deleted.name` hides `name` of lower-priority layers.
+    /// An empty prefix disables deletion markers.
+    pub fn deletion_marker<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.deletion_marker = prefix.into();
+        self
+    }
+
+    /// Read leaves from environment variables rather than files, if there
+    /// are any (see `EnvOverrides`)
+    pub fn env_overrides(mut self, env_overrides: EnvOverrides) -> Self {
+        self.env_overrides = Some(env_overrides);
+        self
+    }
+}
+
+/// State shared by all deserializers of a tree
+struct Context {
+    root: Root,
+    /// Path of the root deserializer (environment overrides are looked up
+    /// relative to it)
+    base: PathBuf,
+    backend: Arc<dyn Backend>,
+    options: DeserializerOptions,
+}
+
+/// Creates a deserializer for `path`, failing unless it stays within the root directory
+fn child<P: AsRef<Path>>(context: &Arc<Context>, path: P) -> Result<FilesystemDeserializer<P>, Error> {
+    if context.root.contains(path.as_ref(), &*context.backend)? {
+        Ok(FilesystemDeserializer { path, context: context.clone() })
+    } else {
+        Err(Error::PathTraversal(path.as_ref().to_path_buf()))
+    }
+}
+
 #[derive(Clone)]
 pub struct FilesystemDeserializer<P: AsRef<Path>> {
     path: P,
+    context: Arc<Context>,
 }
 
 impl<P: AsRef<Path>> FilesystemDeserializer<P> {
     pub fn new(path: P) -> Self {
-        FilesystemDeserializer { path }
+        FilesystemDeserializer::with_options(path, DeserializerOptions::default())
+    }
+
+    /// Creates a deserializer with given options
+    ///
+    /// The deserializer will never read anything outside of `path`.
+    pub fn with_options(path: P, options: DeserializerOptions) -> Self {
+        FilesystemDeserializer::with_backend(path, options, Arc::new(StdFs::default()))
+    }
+
+    /// Creates a deserializer reading from a given backend
+    pub fn with_backend(path: P, options: DeserializerOptions, backend: Arc<dyn Backend>) -> Self {
+        let root = Root::new(path.as_ref(), &*backend);
+        let base = path.as_ref().to_path_buf();
+        FilesystemDeserializer { path, context: Arc::new(Context { root, base, backend, options }) }
     }
+
+    /// Descends into a subtree addressed by pointer segments
+    ///
+    /// The returned deserializer is still confined to the original root.
+    pub(crate) fn descend<S: AsRef<str>>(&self, segments: &[S]) -> Result<FilesystemDeserializer<PathBuf>, Error> {
+        let mut deserializer = child(&self.context, self.path.as_ref().to_path_buf())?;
+        for (i, segment) in segments.iter().enumerate() {
+            let name = self.context.options.key_codec.encode(segment.as_ref());
+            if !is_local_name(&name) {
+                return Err(Error::PathTraversal(deserializer.path.join(name)));
+            }
+            deserializer = child(&self.context, deserializer.path.join(name))?;
+            if deserializer.kind()?.is_none() {
+                return Err(Error::MissingSegment(pointer::join(&segments[..=i])));
+            }
+        }
+        Ok(deserializer)
+    }
+
+    /// Environment variable overriding the leaf at this path, if any
+    fn env_override(&self) -> Option<&str> {
+        let env_overrides = self.context.options.env_overrides.as_ref()?;
+        let path = self.path.as_ref().strip_prefix(&self.context.base).ok()?;
+        env_overrides.get(path, &*self.context.options.key_codec)
+    }
+
+    fn kind(&self) -> Result<Option<Kind>, io::Error> {
+        if self.env_override().is_some() {
+            return Ok(Some(Kind::File));
+        }
+        self.context.backend.kind(self.path.as_ref())
+    }
+
+    fn bytes(&self) -> Result<Vec<u8>, io::Error> {
+        if let Some(value) = self.env_override() {
+            return Ok(value.as_bytes().to_vec());
+        }
+        self.context.backend.read(self.path.as_ref())
+    }
+
+    fn raw_string(&self) -> Result<String, io::Error> {
+        String::from_utf8(self.bytes()?).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn string(&self) -> Result<String, Error> {
+        let mut s = self.raw_string()?;
+        if self.context.options.trim {
+            return Ok(String::from(s.trim()));
+        }
+        if self.context.options.trailing_newline && s.ends_with('\n') {
+            s.pop();
+            if s.ends_with('\r') {
+                s.pop();
+            }
+        }
+        Ok(s)
+    }
+}
+
+/// Checks whether a directory's entries are exactly `0..n` (with n > 0)
+pub(crate) fn is_seq(backend: &dyn Backend, path: &Path) -> Result<bool, io::Error> {
+    Ok(is_seq_names(&backend.read_dir(path)?))
 }
 
-fn string_from_file<P: AsRef<Path>>(path: P) -> Result<String, io::Error> {
-    let mut file = fs::File::open(path)?;
-    let mut s = String::new();
-    file.read_to_string(&mut s)?;
-    Ok(s)
+/// Checks whether entry names are exactly `0..n` (with n > 0)
+pub(crate) fn is_seq_names(names: &[String]) -> bool {
+    let mut indices = vec![];
+    for name in names {
+        match name.parse::<usize>() {
+            Ok(index) if *name == index.to_string() => indices.push(index),
+            _ => return false,
+        }
+    }
+    indices.sort_unstable();
+    !indices.is_empty() && indices.iter().enumerate().all(|(i, index)| i == *index)
 }
 
-fn bytes_from_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, io::Error> {
-    let mut file = fs::File::open(path)?;
-    let mut b = vec![];
-    file.read_to_end(&mut b)?;
-    Ok(b)
+// Parses `text` only if it is the canonical textual form of the value,
+// so that reading it and writing it back yields the same text
+fn parse_canonical<T: FromStr + ToString>(text: &str) -> Option<T> {
+    text.parse().ok().filter(|v: &T| v.to_string() == text)
 }
 
+// Only accepts plain decimal notation, so that strings like `inf` or `NaN`
+// are not mistaken for numbers
+fn parse_float(text: &str) -> Option<f64> {
+    let numeric = text.chars().any(|c| c.is_ascii_digit()) &&
+        text.chars().all(|c| c.is_ascii_digit() || "-.".contains(c));
+    if numeric { parse_canonical(text) } else { None }
+}
+
+impl<P: AsRef<Path>> FilesystemDeserializer<P> {
+    // Leaf files are strings, unless `infer_types` is set
+    fn deserialize_leaf<'de, V>(self, visitor: V) -> Result<V::Value, Error> where
+        V: Visitor<'de> {
+        let bytes = self.bytes()?;
+        let infer_types = self.context.options.infer_types;
+        if bytes.is_empty() && infer_types {
+            return visitor.visit_unit();
+        }
+        let s = match self.string() {
+            Ok(s) => s,
+            Err(Error::IoError(ref err)) if err.kind() == io::ErrorKind::InvalidData =>
+                return visitor.visit_byte_buf(bytes),
+            Err(err) => return Err(err),
+        };
+        if !infer_types {
+            return visitor.visit_string(s);
+        }
+        if let Some(v) = parse_canonical::<bool>(&s) {
+            visitor.visit_bool(v)
+        } else if let Some(v) = parse_canonical::<u64>(&s) {
+            visitor.visit_u64(v)
+        } else if let Some(v) = parse_canonical::<i64>(&s) {
+            visitor.visit_i64(v)
+        } else if let Some(v) = parse_float(&s) {
+            visitor.visit_f64(v)
+        } else {
+            visitor.visit_string(s)
+        }
+    }
+}
 
 impl<'de, P: AsRef<Path>> Deserializer<'de> for FilesystemDeserializer<P> {
     type Error = Error;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        if self.path.as_ref().is_dir() {
-            self.deserialize_map(visitor)
-        } else if self.path.as_ref().is_file() {
-            visitor.visit_str(&string_from_file(self.path)?)
-        } else {
-            Err(Error::FileNotFound)
+        match self.kind()? {
+            Some(Kind::Dir) => {
+                if is_seq(&*self.context.backend, self.path.as_ref())? {
+                    self.deserialize_seq(visitor)
+                } else {
+                    self.deserialize_map(visitor)
+                }
+            },
+            Some(Kind::File) => self.deserialize_leaf(visitor),
+            None => Err(Error::FileNotFound),
         }
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_bool(string_from_file(self.path)?.trim().parse()?)
+        visitor.visit_bool(self.raw_string()?.trim().parse()?)
     }
 
     fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_i8(string_from_file(self.path)?.trim().parse()?)
+        visitor.visit_i8(self.raw_string()?.trim().parse()?)
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_i16(string_from_file(self.path)?.trim().parse()?)
+        visitor.visit_i16(self.raw_string()?.trim().parse()?)
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_i32(string_from_file(self.path)?.trim().parse()?)
+        visitor.visit_i32(self.raw_string()?.trim().parse()?)
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_i64(string_from_file(self.path)?.trim().parse()?)
+        visitor.visit_i64(self.raw_string()?.trim().parse()?)
     }
 
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_u8(string_from_file(self.path)?.trim().parse()?)
+        visitor.visit_u8(self.raw_string()?.trim().parse()?)
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_u16(string_from_file(self.path)?.trim().parse()?)
+        visitor.visit_u16(self.raw_string()?.trim().parse()?)
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_u32(string_from_file(self.path)?.trim().parse()?)
+        visitor.visit_u32(self.raw_string()?.trim().parse()?)
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_u64(string_from_file(self.path)?.trim().parse()?)
+        visitor.visit_u64(self.raw_string()?.trim().parse()?)
     }
 
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_f32(string_from_file(self.path)?.trim().parse()?)
+        visitor.visit_f32(self.raw_string()?.trim().parse()?)
     }
 
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_f64(string_from_file(self.path)?.trim().parse()?)
+        visitor.visit_f64(self.raw_string()?.trim().parse()?)
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        let file = fs::File::open(self.path)?;
-        // FIXME: replace with .chars() if it will become available:
-        // https://github.com/rust-lang/rust/issues/27802
-        // or an alternative solution
-        let mut bytes = file.bytes();
-        match bytes.next() {
+        // FIXME: decode the first character rather than the first byte
+        match self.bytes()?.first() {
             None => Err(Error::Empty),
-            Some(c) => visitor.visit_char(c?.into()),
+            Some(c) => visitor.visit_char((*c).into()),
         }
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_str(&string_from_file(self.path)?)
+        visitor.visit_str(&self.string()?)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_string(string_from_file(self.path)?)
+        visitor.visit_string(self.string()?)
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_bytes(&bytes_from_file(self.path)?)
+        visitor.visit_bytes(&self.bytes()?)
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_byte_buf(bytes_from_file(self.path)?)
+        visitor.visit_byte_buf(self.bytes()?)
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        if !self.path.as_ref().exists() {
+        if self.kind()?.is_none() {
             visitor.visit_none()
         } else {
             visitor.visit_some(self)
@@ -171,7 +437,7 @@ impl<'de, P: AsRef<Path>> Deserializer<'de> for FilesystemDeserializer<P> {
 
     fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        if self.path.as_ref().is_file() {
+        if self.kind()? == Some(Kind::File) {
             visitor.visit_unit()
         } else {
             Err(Error::FileNotFound)
@@ -190,13 +456,13 @@ impl<'de, P: AsRef<Path>> Deserializer<'de> for FilesystemDeserializer<P> {
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        let seq_access = SeqAccess { path: self.path, counter: 0, len: None };
+        let seq_access = SeqAccess { path: self.path, context: self.context, counter: 0, len: None };
         visitor.visit_seq(seq_access)
     }
 
     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        let seq_access = SeqAccess { path: self.path, counter: 0, len: Some(len) };
+        let seq_access = SeqAccess { path: self.path, context: self.context, counter: 0, len: Some(len) };
         visitor.visit_seq(seq_access)
     }
 
@@ -207,27 +473,30 @@ impl<'de, P: AsRef<Path>> Deserializer<'de> for FilesystemDeserializer<P> {
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_map(MapAccess::new(self.path)?)
+        visitor.visit_map(MapAccess::new(self.path, self.context)?)
     }
 
     fn deserialize_struct<V>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_map(MapAccess::new_for(self.path, fields.into_iter().map(|f| Ok(String::from(*f))))?)
+        let names: Vec<_> = fields.iter().map(|f| Ok(self.context.options.key_codec.encode(f))).collect();
+        visitor.visit_map(MapAccess::new_for(self.path, self.context, names.into_iter())?)
     }
 
     fn deserialize_enum<V>(self, _name: &'static str, variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
         use serde::de::IntoDeserializer;
-        let variant = if self.path.as_ref().is_dir() {
-            string_from_file(self.path.as_ref().join("variant"))?
+        // Variant names are read like strings, honoring `trim` and `trailing_newline`
+        let is_dir = self.kind()? == Some(Kind::Dir);
+        let variant = if is_dir {
+            child(&self.context, self.path.as_ref().join("variant"))?.string()?
         } else {
-            string_from_file(self.path.as_ref())?
+            self.string()?
         };
         for available_variant in variants {
            if available_variant == &&variant {
-               if self.path.as_ref().is_dir() {
+               if is_dir {
                    // not a unit enum
-                   return visitor.visit_enum(VariantAccess { path: self.path, variant });
+                   return visitor.visit_enum(VariantAccess { path: self.path, context: self.context, variant });
                } else {
                    return visitor.visit_enum(variant.into_deserializer());
                }
@@ -238,7 +507,7 @@ impl<'de, P: AsRef<Path>> Deserializer<'de> for FilesystemDeserializer<P> {
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        visitor.visit_str(&string_from_file(self.path)?)
+        visitor.visit_str(&self.string()?)
     }
 
     fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
@@ -249,6 +518,7 @@ impl<'de, P: AsRef<Path>> Deserializer<'de> for FilesystemDeserializer<P> {
 
 struct SeqAccess<P: AsRef<Path>> {
     path: P,
+    context: Arc<Context>,
     counter: usize,
     len: Option<usize>,
 }
@@ -258,18 +528,17 @@ impl<'de, P: AsRef<Path>> de::SeqAccess<'de> for SeqAccess<P> {
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> where
         T: de::DeserializeSeed<'de> {
-        let path = self.path.as_ref().join(format!("{}", self.counter));
-        if path.exists() {
+        let element = child(&self.context, self.path.as_ref().join(format!("{}", self.counter)))?;
+        if element.kind()?.is_some() {
             self.counter += 1;
-            Ok(Some(seed.deserialize(FilesystemDeserializer::new(path))?))
+            Ok(Some(seed.deserialize(element)?))
         } else {
-            if self.len.is_some() && self.len.clone().unwrap() != self.counter {
-                Err(Error::InvalidLen {
-                    expected: self.len.clone().unwrap(),
+            match self.len {
+                Some(len) if len != self.counter => Err(Error::InvalidLen {
+                    expected: len,
                     got: self.counter,
-                })
-            } else {
-                Ok(None)
+                }),
+                _ => Ok(None),
             }
         }
     }
@@ -278,6 +547,7 @@ impl<'de, P: AsRef<Path>> de::SeqAccess<'de> for SeqAccess<P> {
 
 struct VariantAccess<P: AsRef<Path>> {
     path: P,
+    context: Arc<Context>,
     variant: String,
 }
 
@@ -290,17 +560,17 @@ impl<'a, 'de, P: AsRef<Path>> de::VariantAccess<'de> for VariantAccess<P> {
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error> where
         T: de::DeserializeSeed<'de> {
-       seed.deserialize(FilesystemDeserializer::new(self.path.as_ref().join("value")))
+       seed.deserialize(child(&self.context, self.path.as_ref().join("value"))?)
     }
 
     fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        FilesystemDeserializer::new(self.path.as_ref()).deserialize_tuple(len, visitor)
+        FilesystemDeserializer { path: self.path, context: self.context }.deserialize_tuple(len, visitor)
     }
 
     fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> where
         V: Visitor<'de> {
-        FilesystemDeserializer::new(self.path.as_ref()).deserialize_struct("", fields, visitor)
+        FilesystemDeserializer { path: self.path, context: self.context }.deserialize_struct("", fields, visitor)
     }
 }
 
@@ -320,29 +590,31 @@ impl<'a, 'de, P: AsRef<Path>> de::EnumAccess<'de> for VariantAccess<P> {
 
 struct MapAccess<P: AsRef<Path>> {
     path: P,
-    dir: Box<Iterator<Item = Result<String, io::Error>>>,
+    context: Arc<Context>,
+    dir: Box<dyn Iterator<Item = Result<String, io::Error>>>,
     key: Option<String>,
+    fields: bool,
 }
 
 impl<P: AsRef<Path>> MapAccess<P> {
-    fn new(path: P) -> Result<Self, Error> {
-        let dir = Box::new(fs::read_dir(path.as_ref())?
-            .map(|res| match res {
-                Ok(file) => Ok(file.file_name().into_string().unwrap()),
-                Err(err) => Err(err),
-            }));
+    fn new(path: P, context: Arc<Context>) -> Result<Self, Error> {
+        let dir = Box::new(context.backend.read_dir(path.as_ref())?.into_iter().map(Ok));
         Ok(MapAccess {
             path,
+            context,
             dir,
             key: None,
+            fields: false,
         })
     }
-    fn new_for<I: Iterator<Item = Result<String, io::Error>> + 'static>(path: P, iter: I) -> Result<Self, Error>
+    fn new_for<I: Iterator<Item = Result<String, io::Error>> + 'static>(path: P, context: Arc<Context>, iter: I) -> Result<Self, Error>
     {
         Ok(MapAccess {
             path,
+            context,
             dir: Box::new(iter),
             key: None,
+            fields: true,
         })
     }
 }
@@ -352,16 +624,20 @@ impl<'de, P: AsRef<Path>> de::MapAccess<'de> for MapAccess<P> {
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> where
         K: de::DeserializeSeed<'de> {
-        use serde::de::IntoDeserializer;
-        use serde::de::value;
-        match self.dir.next() {
-            Some(Ok(name)) => {
-                self.key = Some(name.clone());
-                let deserializer: value::StringDeserializer<Self::Error> = name.into_deserializer();
-                Ok(Some(seed.deserialize(deserializer)?))
-            },
-            Some(Err(e)) => Err(e.into()),
-            None => Ok(None),
+        loop {
+            match self.dir.next() {
+                Some(Ok(name)) => {
+                    // Names the codec rejects are auxiliary files, not entries
+                    let key = match self.context.options.key_codec.decode(&name) {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    self.key = Some(name);
+                    return Ok(Some(seed.deserialize(KeyDeserializer { key })?));
+                },
+                Some(Err(e)) => return Err(e.into()),
+                None => return Ok(None),
+            }
         }
     }
 
@@ -369,18 +645,118 @@ impl<'de, P: AsRef<Path>> de::MapAccess<'de> for MapAccess<P> {
         V: de::DeserializeSeed<'de> {
         match self.key.take() {
             None => Err(Error::FileNotFound),
-            Some(key) =>
-              seed.deserialize(FilesystemDeserializer::new(self.path.as_ref().join(key))),
+            Some(ref key) if self.fields && !is_field_name(key) => Err(Error::PathTraversal(self.path.as_ref().join(key))),
+            Some(ref key) if !self.fields && !is_local_name(key) => Err(Error::PathTraversal(self.path.as_ref().join(key))),
+            Some(key) => seed.deserialize(child(&self.context, self.path.as_ref().join(key))?),
+        }
+    }
+}
+
+/// Parses map keys from their textual form
+///
+/// Mirrors the serializer's `MapKeySerializer`: numbers, booleans, characters
+/// and unit variants are parsed, anything else is passed on as a string.
+struct KeyDeserializer {
+    key: String,
+}
+
+impl<'de> Deserializer<'de> for KeyDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_string(self.key)
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_bool(self.key.parse()?)
+    }
+
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_i8(self.key.parse()?)
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_i16(self.key.parse()?)
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_i32(self.key.parse()?)
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_i64(self.key.parse()?)
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_u8(self.key.parse()?)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_u16(self.key.parse()?)
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_u32(self.key.parse()?)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_u64(self.key.parse()?)
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_f32(self.key.parse()?)
+    }
+
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_f64(self.key.parse()?)
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        let mut chars = self.key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(Error::InvalidKey(self.key)),
         }
     }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> where
+        V: Visitor<'de> {
+        use serde::de::IntoDeserializer;
+        visitor.visit_enum(self.key.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf option unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
 
+    use std::fs;
+
     use super::*;
-    use super::super::FilesystemSerializer;
+    use super::super::{FilesystemSerializer, SerializerOptions, PercentEncoding};
     use tempdir::TempDir;
 
     use serde::{Serialize, Deserialize};
@@ -389,9 +765,7 @@ mod tests {
     fn boolean() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("bool"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("bool"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("bool"));
         true.serialize(serializer.clone()).unwrap();
         assert_eq!(bool::deserialize(deserializer.clone()).unwrap(), true);
         false.serialize(serializer.clone()).unwrap();
@@ -402,9 +776,7 @@ mod tests {
     fn boolean_extra_space() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("bool"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("bool"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("bool"));
         " true \n".serialize(serializer.clone()).unwrap();
         assert_eq!(bool::deserialize(deserializer.clone()).unwrap(), true);
         " false \n".serialize(serializer.clone()).unwrap();
@@ -415,9 +787,7 @@ mod tests {
     fn numbers() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("n"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("n"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("n"));
         (-1i8).serialize(serializer.clone()).unwrap();
         assert_eq!(i8::deserialize(deserializer.clone()).unwrap(), -1);
         (1u8).serialize(serializer.clone()).unwrap();
@@ -444,9 +814,7 @@ mod tests {
     fn numbers_extra_space() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("n"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("n"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("n"));
         " -1 \n".serialize(serializer.clone()).unwrap();
         assert_eq!(i8::deserialize(deserializer.clone()).unwrap(), -1);
         " 1 \n".serialize(serializer.clone()).unwrap();
@@ -474,9 +842,7 @@ mod tests {
     fn char() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("c"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("c"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("c"));
         '!'.serialize(serializer.clone()).unwrap();
         assert_eq!(char::deserialize(deserializer.clone()).unwrap(), '!');
     }
@@ -485,9 +851,7 @@ mod tests {
     fn char_empty() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("c"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("c"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("c"));
         "".serialize(serializer.clone()).unwrap();
         let err = char::deserialize(deserializer.clone()).unwrap_err();
         assert_matches!(err, Error::Empty);
@@ -497,20 +861,36 @@ mod tests {
     fn string() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("str"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("str"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("str"));
         "hello".serialize(serializer.clone()).unwrap();
         assert_eq!(String::deserialize(deserializer.clone()).unwrap(), "hello");
     }
 
+    #[test]
+    fn string_trailing_newline() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("str"), SerializerOptions::new().trailing_newline(true));
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("str"), DeserializerOptions::new().trailing_newline(true));
+        " hello ".serialize(serializer.clone()).unwrap();
+        assert_eq!(String::deserialize(deserializer.clone()).unwrap(), " hello ");
+        "hello\n".serialize(serializer.clone()).unwrap();
+        assert_eq!(String::deserialize(deserializer.clone()).unwrap(), "hello\n");
+    }
+
+    #[test]
+    fn string_trim() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("str"));
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("str"), DeserializerOptions::new().trim(true));
+        " hello \n".serialize(serializer.clone()).unwrap();
+        assert_eq!(String::deserialize(deserializer.clone()).unwrap(), "hello");
+    }
+
     #[test]
     fn bytes() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("bytes"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("bytes"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("bytes"));
         use serde_bytes::{Bytes, ByteBuf};
         Bytes::new(b"hello").serialize(serializer.clone()).unwrap();
         let byte_buf = ByteBuf::deserialize(deserializer.clone()).unwrap();
@@ -522,9 +902,7 @@ mod tests {
     fn option() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("option"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("option"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("option"));
         Some("hello").serialize(serializer.clone()).unwrap();
         assert_eq!(Option::<String>::deserialize(deserializer.clone()).unwrap().unwrap(), "hello");
         None::<Option<&str>>.serialize(serializer.clone()).unwrap();
@@ -535,9 +913,7 @@ mod tests {
     fn unit() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("unit"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("unit"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("unit"));
         ().serialize(serializer.clone()).unwrap();
         assert_eq!(<()>::deserialize(deserializer.clone()).unwrap(), ());
     }
@@ -545,9 +921,7 @@ mod tests {
     #[test]
     fn unit_not_found() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("unit"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("unit"));
         let err = <()>::deserialize(deserializer.clone()).unwrap_err();
         assert_matches!(err, Error::FileNotFound);
     }
@@ -559,9 +933,7 @@ mod tests {
     fn unit_struct() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("unit"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("unit"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("unit"));
         UnitStruct.serialize(serializer.clone()).unwrap();
         assert_eq!(UnitStruct::deserialize(deserializer.clone()).unwrap(), UnitStruct);
     }
@@ -578,9 +950,7 @@ mod tests {
     fn unit_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("unit"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("unit"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("unit"));
         UnitVariant::B.serialize(serializer.clone()).unwrap();
         assert_eq!(UnitVariant::deserialize(deserializer.clone()).unwrap(), UnitVariant::B);
     }
@@ -596,9 +966,7 @@ mod tests {
     fn unit_wrong_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("unit"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("unit"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("unit"));
         UnitVariant::B.serialize(serializer.clone()).unwrap();
         let err = UnitVariant1::deserialize(deserializer.clone()).unwrap_err();
         assert_matches!(err, Error::InvalidEnum(ref str) if str == "b");
@@ -611,9 +979,7 @@ mod tests {
     fn newtype_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("newtype"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("newtype"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("newtype"));
         NewTypeVariant(UnitVariant::B).serialize(serializer.clone()).unwrap();
         assert_eq!(NewTypeVariant::deserialize(deserializer.clone()).unwrap(), NewTypeVariant(UnitVariant::B));
     }
@@ -625,9 +991,7 @@ mod tests {
     fn newtype_struct() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("newtype"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("newtype"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("newtype"));
         NewTypeStruct(100).serialize(serializer.clone()).unwrap();
         assert_eq!(NewTypeStruct::deserialize(deserializer.clone()).unwrap(), NewTypeStruct(100));
     }
@@ -639,9 +1003,7 @@ mod tests {
     fn newtype_tuple_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("newtype"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("newtype"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("newtype"));
         let v = NewTypeTupleVariant(TupleVariant::V1(100, 100, 100));
         v.serialize(serializer.clone()).unwrap();
         assert_eq!(NewTypeTupleVariant::deserialize(deserializer.clone()).unwrap(), v);
@@ -656,9 +1018,7 @@ mod tests {
     fn tuple_newtype_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("var"));
-         let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("var"),
-        };
+         let deserializer = FilesystemDeserializer::new(tmp.path().join("var"));
         VariantNewType::C(100).serialize(serializer.clone()).unwrap();
         assert_eq!(VariantNewType::deserialize(deserializer.clone()).unwrap(), VariantNewType::C(100));
     }
@@ -673,9 +1033,7 @@ mod tests {
     fn tuple_newtype_variant_tag() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("var"));
-         let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("var"),
-        };
+         let deserializer = FilesystemDeserializer::new(tmp.path().join("var"));
         VariantNewTypeTag::C(100).serialize(serializer.clone()).unwrap();
         assert_eq!(VariantNewTypeTag::deserialize(deserializer.clone()).unwrap(), VariantNewTypeTag::C(100));
     }
@@ -684,9 +1042,7 @@ mod tests {
     fn seq() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("seq"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("seq"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("seq"));
         vec![100,200,300].serialize(serializer.clone()).unwrap();
         assert_eq!(Vec::<u16>::deserialize(deserializer.clone()).unwrap(), vec![100, 200, 300]);
     }
@@ -710,9 +1066,7 @@ mod tests {
 
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("seq-complex-elem"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("seq-complex-elem"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("seq-complex-elem"));
 
         let value = vec![
             Complex::new(0),
@@ -727,9 +1081,7 @@ mod tests {
     fn tuple() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("tuple"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("tuple"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("tuple"));
         (100,200,300).serialize(serializer.clone()).unwrap();
         assert_eq!(<(u8, u8, u16)>::deserialize(deserializer.clone()).unwrap(), (100, 200, 300));
     }
@@ -738,9 +1090,7 @@ mod tests {
     fn tuple_size_mismatch() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("tuple"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("tuple"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("tuple"));
         (100,200).serialize(serializer.clone()).unwrap();
         let err = <(u8, u8, u16)>::deserialize(deserializer.clone()).unwrap_err();
         assert_matches!(err, Error::InvalidLen { expected: 3, got: 2 });
@@ -750,9 +1100,7 @@ mod tests {
     fn tuple_type_mismatch() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("tuple"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("tuple"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("tuple"));
         (100,"hello").serialize(serializer.clone()).unwrap();
         let err = <(u8, u8)>::deserialize(deserializer.clone()).unwrap_err();
         assert_matches!(err, Error::ParseIntError(_));
@@ -765,9 +1113,7 @@ mod tests {
     fn tuple_struct() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("tuple"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("tuple"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("tuple"));
         TupleStruct(100,200,100).serialize(serializer.clone()).unwrap();
         assert_eq!(TupleStruct::deserialize(deserializer.clone()).unwrap(), TupleStruct(100, 200, 100));
     }
@@ -776,9 +1122,7 @@ mod tests {
     fn tuple_struct_type_mismatch() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("tuple"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("tuple"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("tuple"));
         (100,"hello", 100).serialize(serializer.clone()).unwrap();
         let err = TupleStruct::deserialize(deserializer.clone()).unwrap_err();
         assert_matches!(err, Error::ParseIntError(_));
@@ -789,9 +1133,7 @@ mod tests {
     fn tuple_struct_size_mismatch() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("tuple"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("tuple"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("tuple"));
         (100,200).serialize(serializer.clone()).unwrap();
         let err = TupleStruct::deserialize(deserializer.clone()).unwrap_err();
         assert_matches!(err, Error::InvalidLen { expected: 3, got: 2 });
@@ -806,9 +1148,7 @@ mod tests {
     fn tuple_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("tuple"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("tuple"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("tuple"));
         TupleVariant::V1(100,200,100).serialize(serializer.clone()).unwrap();
         assert_eq!(TupleVariant::deserialize(deserializer.clone()).unwrap(), TupleVariant::V1(100, 200, 100));
     }
@@ -817,9 +1157,7 @@ mod tests {
     fn map() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("map"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("map"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("map"));
         use std::collections::HashMap;
         let mut map = HashMap::new();
         map.insert("test".into(), 100);
@@ -828,6 +1166,112 @@ mod tests {
         assert_eq!(HashMap::<String, i32>::deserialize(deserializer.clone()).unwrap(), map);
     }
 
+    #[test]
+    fn map_key_codec() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("map"), SerializerOptions::new().key_codec(PercentEncoding));
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("map"), DeserializerOptions::new().key_codec(PercentEncoding));
+        use std::collections::HashMap;
+        let mut map = HashMap::new();
+        for key in &["", "a/b", "..", ".", ".hidden", "x\0y", "../../etc/passwd", "100%"] {
+            map.insert(String::from(*key), 1);
+        }
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+        assert_eq!(HashMap::<String, i32>::deserialize(deserializer.clone()).unwrap(), map);
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+    enum Key {
+        First,
+        Second,
+    }
+
+    #[test]
+    fn map_non_string_keys() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("map"));
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("map"));
+        use std::collections::BTreeMap;
+        let mut map = BTreeMap::new();
+        map.insert(1u32, 100);
+        map.insert(2100, 200);
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(BTreeMap::<u32, u32>::deserialize(deserializer.clone()).unwrap(), map);
+        fs::remove_dir_all(tmp.path().join("map")).unwrap();
+        let mut map = BTreeMap::new();
+        map.insert(Key::First, 'a');
+        map.insert(Key::Second, 'b');
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(BTreeMap::<Key, char>::deserialize(deserializer.clone()).unwrap(), map);
+        fs::remove_dir_all(tmp.path().join("map")).unwrap();
+        let mut map = BTreeMap::new();
+        map.insert('x', true);
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(BTreeMap::<char, bool>::deserialize(deserializer.clone()).unwrap(), map);
+        let err = BTreeMap::<u32, bool>::deserialize(deserializer.clone()).unwrap_err();
+        assert_matches!(err, Error::ParseIntError(_));
+    }
+
+    #[test]
+    fn map_key_codec_invalid_name() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::create_dir_all(tmp.path().join("map")).unwrap();
+        fs::write(tmp.path().join("map").join("%zz"), "1").unwrap();
+        fs::write(tmp.path().join("map").join("notes.txt%"), "1").unwrap();
+        fs::write(tmp.path().join("map").join("a%2Fb"), "2").unwrap();
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("map"), DeserializerOptions::new().key_codec(PercentEncoding));
+        use std::collections::HashMap;
+        let map = HashMap::<String, i32>::deserialize(deserializer.clone()).unwrap();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["a/b"], 2);
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct RenamedStruct {
+        #[serde(rename = "a/b")]
+        test: u8,
+    }
+
+    #[test]
+    fn structure_key_codec() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("struct"), SerializerOptions::new().key_codec(PercentEncoding));
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("struct"), DeserializerOptions::new().key_codec(PercentEncoding));
+        let s = RenamedStruct { test: 100 };
+        s.serialize(serializer.clone()).unwrap();
+        assert!(tmp.path().join("struct").join("a%2Fb").is_file());
+        assert_eq!(RenamedStruct::deserialize(deserializer.clone()).unwrap(), s);
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct EscapingStruct {
+        #[serde(rename = "../secret")]
+        _secret: String,
+    }
+
+    #[test]
+    fn structure_traversal() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::create_dir_all(tmp.path().join("struct")).unwrap();
+        fs::write(tmp.path().join("secret"), "secret").unwrap();
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("struct"));
+        assert_matches!(EscapingStruct::deserialize(deserializer.clone()), Err(Error::PathTraversal(_)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_traversal() {
+        use std::os::unix::fs::symlink;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::create_dir_all(tmp.path().join("struct")).unwrap();
+        fs::write(tmp.path().join("secret"), "1").unwrap();
+        fs::write(tmp.path().join("struct").join("passed"), "2").unwrap();
+        symlink(tmp.path().join("secret"), tmp.path().join("struct").join("test")).unwrap();
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("struct"));
+        assert_matches!(Struct::deserialize(deserializer.clone()), Err(Error::PathTraversal(_)));
+    }
+
     #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Default, Debug)]
     struct Struct {
         test: u8,
@@ -838,9 +1282,7 @@ mod tests {
     fn structure() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("struct"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("struct"));
         let s = Struct {
             test: 100,
             passed: 2100,
@@ -860,9 +1302,7 @@ mod tests {
     fn structure_extra() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("struct"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("struct"));
         let s = Struct1 {
             test: 100,
             passed: 2100,
@@ -884,9 +1324,20 @@ mod tests {
     fn struct_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("struct"),
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("struct"));
+        let s = StructVariant::V1 {
+            test: 100,
+            passed: 2100,
         };
+        s.serialize(serializer.clone()).unwrap();
+        assert_eq!(StructVariant::deserialize(deserializer.clone()).unwrap(), s);
+    }
+
+    #[test]
+    fn struct_variant_trailing_newline() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("struct"), SerializerOptions::new().trailing_newline(true));
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("struct"), DeserializerOptions::new().trailing_newline(true));
         let s = StructVariant::V1 {
             test: 100,
             passed: 2100,
@@ -895,6 +1346,16 @@ mod tests {
         assert_eq!(StructVariant::deserialize(deserializer.clone()).unwrap(), s);
     }
 
+    #[test]
+    fn unit_variant_trim() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::write(tmp.path().join("variant"), " C \n").unwrap();
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("variant"));
+        assert!(UnitVariant::deserialize(deserializer).is_err());
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("variant"), DeserializerOptions::new().trim(true));
+        assert_eq!(UnitVariant::deserialize(deserializer).unwrap(), UnitVariant::C);
+    }
+
     #[derive(Serialize, Deserialize, Debug, PartialEq)]
     #[serde(tag = "type", content = ".")]
     enum StructVariantTagInternal {
@@ -908,9 +1369,7 @@ mod tests {
     fn struct_variant_tag_internal() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("struct"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("struct"));
         let s = StructVariantTagInternal::V1 {
             test: 100,
             passed: 2100,
@@ -919,9 +1378,8 @@ mod tests {
         assert_eq!(StructVariantTagInternal::deserialize(deserializer.clone()).unwrap(), s);
     }
 
-    // The test below is currently expected to fail as there
-    // is no known solution to the issue. The test above (with content set to ".")
-    // replicates the intended behaviour of this one and works. This one doesn't
+    // This one relies on `DeserializerOptions::infer_types`, as the test above
+    // (with content set to ".") doesn't
     // See issue e8310019-9017-4ce8-9397-800ca44300ce
     #[derive(Serialize, Deserialize, Debug, PartialEq)]
     #[serde(tag = "type")]
@@ -933,13 +1391,10 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "invalid type")]
     fn struct_variant_tag() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("struct"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("struct"));
         let s = StructVariantTag::V1 {
             test: 100,
             passed: 2100,
@@ -950,6 +1405,226 @@ mod tests {
 
 
 
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(tag = "type")]
+    enum Tagged {
+        V1 {
+            name: String,
+            enabled: bool,
+            offset: i32,
+            ratio: f64,
+        },
+        V2(Struct),
+    }
+
+    #[test]
+    fn struct_variant_tag_typed() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("tagged"));
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("tagged"));
+        let s = Tagged::V1 {
+            name: "inf".into(),
+            enabled: true,
+            offset: -1,
+            ratio: 0.5,
+        };
+        s.serialize(serializer.clone()).unwrap();
+        assert_eq!(Tagged::deserialize(deserializer.clone()).unwrap(), s);
+        fs::remove_dir_all(tmp.path().join("tagged")).unwrap();
+        let s = Tagged::V2(Struct { test: 1, passed: 2 });
+        s.serialize(serializer.clone()).unwrap();
+        assert_eq!(Tagged::deserialize(deserializer.clone()).unwrap(), s);
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(tag = "type")]
+    enum Named {
+        V1 {
+            name: String,
+        },
+    }
+
+    #[test]
+    fn struct_variant_tag_numeric_string() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("tagged"));
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("tagged"), DeserializerOptions::new().infer_types(false));
+        let inferring = FilesystemDeserializer::new(tmp.path().join("tagged"));
+        for name in &["80", "true", "", "-1.5"] {
+            let s = Named::V1 { name: String::from(*name) };
+            s.serialize(serializer.clone()).unwrap();
+            assert_eq!(Named::deserialize(deserializer.clone()).unwrap(), s);
+        }
+        for name in &["080", "+5", "1.50", " true ", "1e3", "80\n"] {
+            let s = Named::V1 { name: String::from(*name) };
+            s.serialize(serializer.clone()).unwrap();
+            assert_eq!(Named::deserialize(deserializer.clone()).unwrap(), s);
+            assert_eq!(Named::deserialize(inferring.clone()).unwrap(), s);
+        }
+    }
+
+    #[test]
+    fn pointer() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("seq"));
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("seq"));
+        vec![Struct { test: 1, passed: 2 }, Struct { test: 3, passed: 4 }].serialize(serializer.clone()).unwrap();
+        assert_eq!(u64::deserialize(deserializer.descend(&["1", "passed"]).unwrap()).unwrap(), 4);
+        assert_eq!(Struct::deserialize(deserializer.descend(&["0"]).unwrap()).unwrap(), Struct { test: 1, passed: 2 });
+        assert_matches!(deserializer.descend(&["2", "test"]).err(), Some(Error::MissingSegment(ref pointer)) if pointer == "2");
+        assert_matches!(deserializer.descend(&["0", "a/b"]).err(), Some(Error::PathTraversal(_)));
+        assert_matches!(deserializer.descend(&["."]).err(), Some(Error::PathTraversal(_)));
+        assert_matches!(deserializer.descend(&["..", "seq"]).err(), Some(Error::PathTraversal(_)));
+    }
+
+    #[test]
+    fn pointer_key_codec() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("struct"), SerializerOptions::new().key_codec(PercentEncoding));
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("struct"), DeserializerOptions::new().key_codec(PercentEncoding));
+        RenamedStruct { test: 100 }.serialize(serializer.clone()).unwrap();
+        assert_eq!(u8::deserialize(deserializer.descend(&["a/b"]).unwrap()).unwrap(), 100);
+        assert_matches!(deserializer.descend(&[".."]).err(), Some(Error::MissingSegment(_)));
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Configuration {
+        servers: Vec<Struct>,
+        mode: UnitVariant,
+        limit: Option<u16>,
+    }
+
+    #[test]
+    fn env_overrides() {
+        use super::super::EnvOverrides;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("config"));
+        let s = Configuration { servers: vec![Struct { test: 1, passed: 2 }], mode: UnitVariant::A, limit: None };
+        s.serialize(serializer).unwrap();
+        let overrides = EnvOverrides::from_vars("APP", vec![
+            ("APP__SERVERS__0__PASSED", "3"),
+            ("APP__SERVERS__1__TEST", "4"),
+            ("APP__MODE", "C"),
+            ("APP__LIMIT", "5"),
+        ]);
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("config"), DeserializerOptions::new().env_overrides(overrides));
+        assert_eq!(Configuration::deserialize(deserializer.clone()).unwrap(),
+                   Configuration { servers: vec![Struct { test: 1, passed: 3 }], mode: UnitVariant::C, limit: Some(5) });
+        assert_eq!(u64::deserialize(deserializer.descend(&["servers", "0", "passed"]).unwrap()).unwrap(), 3);
+        assert!(!tmp.path().join("config").join("limit").exists());
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Common {
+        name: String,
+        enabled: bool,
+        ratio: f32,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Flattened {
+        port: u16,
+        #[serde(flatten)]
+        common: Common,
+        #[serde(flatten)]
+        extra: ::std::collections::BTreeMap<String, Struct>,
+    }
+
+    #[test]
+    fn structure_flatten() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("struct"));
+        let mut extra = ::std::collections::BTreeMap::new();
+        extra.insert(String::from("section"), Struct { test: 1, passed: 2 });
+        let s = Flattened {
+            port: 80,
+            common: Common { name: "web".into(), enabled: true, ratio: 0.25 },
+            extra,
+        };
+        s.serialize(serializer.clone()).unwrap();
+        assert_eq!(Flattened::deserialize(deserializer.clone()).unwrap(), s);
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(untagged)]
+    enum Untagged {
+        Unit(()),
+        Number(u64),
+        Flag(bool),
+        Text(String),
+        List(Vec<Untagged>),
+        Struct { test: u8, passed: u64 },
+    }
+
+    #[test]
+    fn untagged() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("untagged"));
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("untagged"));
+        let s = Untagged::List(vec![
+            Untagged::Unit(()),
+            Untagged::Number(100),
+            Untagged::Flag(false),
+            Untagged::Text("Hello".into()),
+            Untagged::List(vec![Untagged::Number(1)]),
+            Untagged::Struct { test: 1, passed: 2 },
+        ]);
+        s.serialize(serializer.clone()).unwrap();
+        assert_eq!(Untagged::deserialize(deserializer.clone()).unwrap(), s);
+    }
+
+    #[test]
+    fn any_value() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::create_dir_all(tmp.path().join("tree").join("seq")).unwrap();
+        fs::create_dir_all(tmp.path().join("tree").join("map")).unwrap();
+        fs::write(tmp.path().join("tree").join("seq").join("0"), "1").unwrap();
+        fs::write(tmp.path().join("tree").join("seq").join("1"), "-1.5").unwrap();
+        fs::write(tmp.path().join("tree").join("map").join("0"), "a").unwrap();
+        fs::write(tmp.path().join("tree").join("map").join("01"), "b").unwrap();
+        fs::write(tmp.path().join("tree").join("unit"), "").unwrap();
+        fs::write(tmp.path().join("tree").join("port"), "080\n").unwrap();
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("tree"), DeserializerOptions::new().infer_types(false));
+        let value = ::serde_json::Value::deserialize(deserializer.clone()).unwrap();
+        assert_eq!(value, json!({
+            "seq": ["1", "-1.5"],
+            "map": { "0": "a", "01": "b" },
+            "unit": "",
+            "port": "080\n",
+        }));
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("tree"));
+        let value = ::serde_json::Value::deserialize(deserializer.clone()).unwrap();
+        assert_eq!(value, json!({
+            "seq": [1, -1.5],
+            "map": { "0": "a", "01": "b" },
+            "unit": null,
+            "port": "080\n",
+        }));
+    }
+
+    // Self-describing deserialization can't tell these apart from other values,
+    // so they don't round-trip (see the README)
+    #[test]
+    fn any_value_ambiguities() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("value"));
+        let deserializer = FilesystemDeserializer::with_options(tmp.path().join("value"), DeserializerOptions::new().infer_types(false));
+        Vec::<u8>::new().serialize(serializer.clone()).unwrap();
+        assert_eq!(::serde_json::Value::deserialize(deserializer.clone()).unwrap(), json!({}));
+        fs::remove_dir_all(tmp.path().join("value")).unwrap();
+        let mut map = ::std::collections::BTreeMap::new();
+        map.insert("0", "a");
+        map.insert("1", "b");
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(::serde_json::Value::deserialize(deserializer.clone()).unwrap(), json!(["a", "b"]));
+        fs::remove_dir_all(tmp.path().join("value")).unwrap();
+        "".serialize(serializer.clone()).unwrap();
+        assert_eq!(::serde_json::Value::deserialize(deserializer.clone()).unwrap(), json!(""));
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("value"));
+        assert_eq!(::serde_json::Value::deserialize(deserializer.clone()).unwrap(), json!(null));
+    }
+
     #[derive(Serialize, Deserialize, Debug, PartialEq)]
     #[serde(tag = "type", content = "content")]
     enum StructVariantTagContent {
@@ -963,9 +1638,7 @@ mod tests {
     fn struct_variant_tag_content() {
         let tmp = TempDir::new("serde-fs").unwrap();
         let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
-        let deserializer = FilesystemDeserializer {
-            path: tmp.path().join("struct"),
-        };
+        let deserializer = FilesystemDeserializer::new(tmp.path().join("struct"));
         let s = StructVariantTagContent::V1 {
             test: 100,
             passed: 2100,
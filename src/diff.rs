@@ -0,0 +1,204 @@
+//! Structural comparison of file system trees
+
+use serde::Serialize;
+
+use ser;
+use de;
+use pointer;
+use value::Value;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    SerializerError(ser::Error),
+    DeserializerError(de::Error),
+}
+
+/// A difference between two trees
+///
+/// Pointers address the differing subtree (see `get`); the root is addressed
+/// by an empty pointer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added {
+        pointer: String,
+        value: Value,
+    },
+    Removed {
+        pointer: String,
+        value: Value,
+    },
+    Changed {
+        pointer: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl Change {
+    pub fn pointer(&self) -> &str {
+        match *self {
+            Change::Added { ref pointer, .. } |
+            Change::Removed { ref pointer, .. } |
+            Change::Changed { ref pointer, .. } => pointer,
+        }
+    }
+}
+
+/// Compares two trees (see `Value::diff`)
+pub(crate) fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = vec![];
+    compare(&mut vec![], old, new, &mut changes);
+    changes
+}
+
+/// Compares the tree at `path` with a value serialized using given options
+pub(crate) fn diff_value<T: ?Sized + Serialize, P: AsRef<::std::path::Path>>(path: P, value: &T, options: &ser::SerializerOptions) -> Result<Vec<Change>, Error> {
+    let old = ::value::read(path.as_ref())?;
+    let new = ::plan::to_value(path, value, options)?;
+    Ok(diff(&old, &new))
+}
+
+fn compare(path: &mut Vec<String>, old: &Value, new: &Value, changes: &mut Vec<Change>) {
+    if old == new {
+        return;
+    }
+    let pointer = || pointer::join(path);
+    match (old, new) {
+        (&Value::Missing, _) => changes.push(Change::Added { pointer: pointer(), value: new.clone() }),
+        (_, &Value::Missing) => changes.push(Change::Removed { pointer: pointer(), value: old.clone() }),
+        _ if is_comparable(old, new) => {
+            let mut names = entries(old);
+            for name in entries(new) {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            for name in names {
+                path.push(name);
+                compare(path, old.child(path.last().unwrap()), new.child(path.last().unwrap()), changes);
+                path.pop();
+            }
+        },
+        _ => changes.push(Change::Changed { pointer: pointer(), old: old.clone(), new: new.clone() }),
+    }
+}
+
+/// Checks whether two trees can be compared entry by entry
+fn is_comparable(old: &Value, new: &Value) -> bool {
+    match (old, new) {
+        (&Value::Dir(_), &Value::Dir(_)) => {
+            let (old_variant, new_variant) = (old.child("variant"), new.child("variant"));
+            old_variant.as_bytes().is_none() || new_variant.as_bytes().is_none() || old_variant == new_variant
+        },
+        (&Value::Seq(_), &Value::Seq(_)) => true,
+        // Empty sequences are stored as empty directories
+        (&Value::Seq(_), &Value::Dir(ref map)) | (&Value::Dir(ref map), &Value::Seq(_)) => map.is_empty(),
+        _ => false,
+    }
+}
+
+fn entries(value: &Value) -> Vec<String> {
+    match *value {
+        Value::Dir(ref map) => map.keys().cloned().collect(),
+        Value::Seq(ref seq) => (0..seq.len()).map(|index| index.to_string()).collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use tempdir::TempDir;
+    use ser::{FilesystemSerializer, SerializerOptions, Prune};
+
+    #[derive(Serialize)]
+    enum Mode {
+        Plain(String),
+        Tls { cert: String },
+    }
+
+    #[derive(Serialize)]
+    struct Server {
+        host: String,
+        ports: Vec<u16>,
+        mode: Mode,
+    }
+
+    fn dir(entries: Vec<(&str, Value)>) -> Value {
+        Value::Dir(entries.into_iter().map(|(name, value)| (String::from(name), value)).collect::<BTreeMap<_, _>>())
+    }
+
+    #[test]
+    fn files() {
+        assert!(diff(&Value::from("a"), &Value::from("a")).is_empty());
+        assert_eq!(diff(&Value::from("a"), &Value::from("b")),
+                   vec![Change::Changed { pointer: String::new(), old: Value::from("a"), new: Value::from("b") }]);
+        assert_eq!(diff(&Value::Missing, &Value::from("b")),
+                   vec![Change::Added { pointer: String::new(), value: Value::from("b") }]);
+    }
+
+    #[test]
+    fn dirs() {
+        let old = dir(vec![("a", Value::from("1")), ("b/c", Value::from("2")), ("d", dir(vec![("e", Value::from("3"))]))]);
+        let new = dir(vec![("a", Value::from("1")), ("d", dir(vec![("e", Value::from("4"))])), ("f", Value::from("5"))]);
+        assert_eq!(diff(&old, &new), vec![
+            Change::Removed { pointer: String::from("b~1c"), value: Value::from("2") },
+            Change::Changed { pointer: String::from("d/e"), old: Value::from("3"), new: Value::from("4") },
+            Change::Added { pointer: String::from("f"), value: Value::from("5") },
+        ]);
+        assert_eq!(diff(&old, &Value::from("1")),
+                   vec![Change::Changed { pointer: String::new(), old: old.clone(), new: Value::from("1") }]);
+    }
+
+    #[test]
+    fn seqs() {
+        let seq = |items: Vec<&str>| Value::Seq(items.into_iter().map(Value::from).collect());
+        let changes = diff(&seq(vec!["1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11"]), &seq(vec!["1", "3"]));
+        assert_eq!(changes[0], Change::Changed { pointer: String::from("1"), old: Value::from("2"), new: Value::from("3") });
+        let removed: Vec<_> = changes[1..].iter().map(|change| change.pointer()).collect();
+        assert_eq!(removed, vec!["2", "3", "4", "5", "6", "7", "8", "9", "10"]);
+        assert_eq!(diff(&seq(vec!["1"]), &dir(vec![])),
+                   vec![Change::Removed { pointer: String::from("0"), value: Value::from("1") }]);
+    }
+
+    #[test]
+    fn variants() {
+        let old = dir(vec![("variant", Value::from("Plain")), ("value", Value::from("x"))]);
+        let new = dir(vec![("variant", Value::from("Tls")), ("cert", Value::from("x"))]);
+        assert_eq!(diff(&old, &new),
+                   vec![Change::Changed { pointer: String::new(), old: old.clone(), new: new.clone() }]);
+        let changed = dir(vec![("variant", Value::from("Plain")), ("value", Value::from("y"))]);
+        assert_eq!(diff(&old, &changed),
+                   vec![Change::Changed { pointer: String::from("value"), old: Value::from("x"), new: Value::from("y") }]);
+    }
+
+    #[test]
+    fn tree_and_value() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let server = Server { host: "localhost".into(), ports: vec![80, 443], mode: Mode::Plain("http".into()) };
+        server.serialize(FilesystemSerializer::new(tmp.path())).unwrap();
+        assert!(diff_value(tmp.path(), &server, &SerializerOptions::new()).unwrap().is_empty());
+        let server = Server { host: "localhost".into(), ports: vec![8080], mode: Mode::Tls { cert: "cert.pem".into() } };
+        let changes = diff_value(tmp.path(), &server, &SerializerOptions::new()).unwrap();
+        let pointers: Vec<_> = changes.iter().map(|change| change.pointer()).collect();
+        assert_eq!(pointers, vec!["mode", "ports/0", "ports/1"]);
+        assert_matches!(changes[2], Change::Removed { .. });
+    }
+
+    #[test]
+    fn tree_and_value_auxiliary_files() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let server = Server { host: "localhost".into(), ports: vec![80], mode: Mode::Plain("http".into()) };
+        server.serialize(FilesystemSerializer::new(tmp.path())).unwrap();
+        fs::write(tmp.path().join("README.md"), "Server").unwrap();
+        assert!(diff_value(tmp.path(), &server, &SerializerOptions::new()).unwrap().is_empty());
+        let options = SerializerOptions::new().prune(Prune::Exact(vec![]));
+        let changes = diff_value(tmp.path(), &server, &options).unwrap();
+        assert_eq!(changes, vec![Change::Removed { pointer: String::from("README.md"), value: Value::from("Server") }]);
+    }
+
+}
@@ -0,0 +1,99 @@
+//! Overriding leaves with environment variables
+
+use std::env;
+use std::path::{Component, Path};
+use std::collections::BTreeMap;
+
+use key::KeyCodec;
+
+/// Environment variables overriding leaves of a tree
+///
+/// With a prefix of `APP`, `APP__SERVERS__0__PORT=8080` overrides
+/// `servers/0/port`: the variable's name past the prefix is split into
+/// segments on `__`, which are matched against map keys (or sequence
+/// indices) case-insensitively.
+///
+/// Overrides take the place of the files they address, whether these
+/// exist or not, but they don't add entries to maps or sequences that are
+/// read in their entirety.
+///
+/// ```
+/// use serde_fs::{DeserializerOptions, EnvOverrides};
+///
+/// let options = DeserializerOptions::new()
+///     .env_overrides(EnvOverrides::from_vars("APP", vec![("APP__SERVERS__0__PORT", "8080")]));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EnvOverrides {
+    vars: BTreeMap<Vec<String>, String>,
+}
+
+impl EnvOverrides {
+    /// Takes overrides from the process environment
+    pub fn new(prefix: &str) -> Self {
+        EnvOverrides::from_vars(prefix, env::vars())
+    }
+
+    /// Takes overrides from given variables (like a snapshot of the environment)
+    pub fn from_vars<I, K, V>(prefix: &str, vars: I) -> Self
+        where I: IntoIterator<Item = (K, V)>, K: AsRef<str>, V: Into<String> {
+        let prefix = format!("{}__", prefix);
+        let mut overrides = BTreeMap::new();
+        for (name, value) in vars {
+            let name = name.as_ref();
+            if !name.starts_with(&prefix) || name.len() == prefix.len() {
+                continue;
+            }
+            let segments = name[prefix.len()..].split("__").map(|segment| segment.to_lowercase()).collect();
+            overrides.insert(segments, value.into());
+        }
+        EnvOverrides { vars: overrides }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// Looks up an override of a path relative to the root
+    pub(crate) fn get(&self, path: &Path, key_codec: &dyn KeyCodec) -> Option<&str> {
+        let mut segments = vec![];
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => {
+                    let name = name.to_string_lossy();
+                    let key = key_codec.decode(&name).unwrap_or_else(|| name.into_owned());
+                    segments.push(key.to_lowercase());
+                },
+                Component::CurDir => (),
+                _ => return None,
+            }
+        }
+        self.vars.get(&segments).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use key::{Verbatim, PercentEncoding};
+
+    #[test]
+    fn lookup() {
+        let overrides = EnvOverrides::from_vars("APP", vec![
+            ("APP__SERVERS__0__PORT", "8080"),
+            ("APP__MAX_CONNECTIONS", "10"),
+            ("APP__", "ignored"),
+            ("APP", "ignored"),
+            ("OTHER__PORT", "ignored"),
+        ]);
+        assert_eq!(overrides.get(Path::new("servers/0/port"), &Verbatim), Some("8080"));
+        assert_eq!(overrides.get(Path::new("max_connections"), &Verbatim), Some("10"));
+        assert_eq!(overrides.get(Path::new("Max_Connections"), &Verbatim), Some("10"));
+        assert_eq!(overrides.get(Path::new("port"), &Verbatim), None);
+        assert_eq!(overrides.get(Path::new(""), &Verbatim), None);
+        let overrides = EnvOverrides::from_vars("APP", vec![("APP__.HIDDEN", "1")]);
+        assert_eq!(overrides.get(Path::new("%2Ehidden"), &PercentEncoding), Some("1"));
+    }
+
+}
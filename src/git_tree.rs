@@ -0,0 +1,166 @@
+//! Trees stored as git objects
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use git2::{ObjectType, Oid, Repository, Tree};
+
+use backend::{Backend, Kind};
+use mem::MemFs;
+use ser::{self, FilesystemSerializer, SerializerOptions};
+use de::{self, FilesystemDeserializer, DeserializerOptions};
+
+const FILE_MODE: i32 = 0o100644;
+const EXECUTABLE_MODE: i32 = 0o100755;
+const TREE_MODE: i32 = 0o040000;
+
+fn git_error(err: ::git2::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+/// Serializes `value` into memory and writes it into `repository` as a tree,
+/// returning the tree's id
+///
+/// Only values serializing to a directory (like structs, maps or sequences)
+/// can be written as a tree.
+pub(crate) fn write<T: ?Sized + Serialize>(repository: &Repository, value: &T, options: &SerializerOptions) -> Result<Oid, ser::Error> {
+    let fs = Arc::new(MemFs::new());
+    value.serialize(FilesystemSerializer::with_backend("tree", options.clone(), fs.clone()))?;
+    if fs.kind(Path::new("tree"))? != Some(Kind::Dir) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "values written to git have to serialize to a directory").into());
+    }
+    let file_mode = match options.file_mode {
+        Some(mode) if mode & 0o111 != 0 => EXECUTABLE_MODE,
+        _ => FILE_MODE,
+    };
+    Ok(write_tree(repository, &*fs, Path::new("tree"), file_mode)?)
+}
+
+fn write_tree(repository: &Repository, fs: &dyn Backend, path: &Path, file_mode: i32) -> Result<Oid, io::Error> {
+    let mut builder = repository.treebuilder(None).map_err(git_error)?;
+    for name in fs.read_dir(path)? {
+        let entry_path = path.join(&name);
+        let (id, mode) = match fs.kind(&entry_path)? {
+            Some(Kind::Dir) => (write_tree(repository, fs, &entry_path, file_mode)?, TREE_MODE),
+            _ => (repository.blob(&fs.read(&entry_path)?).map_err(git_error)?, file_mode),
+        };
+        builder.insert(&name, id, mode).map_err(git_error)?;
+    }
+    builder.write().map_err(git_error)
+}
+
+/// Reads a tree of `repository` into memory and deserializes it
+///
+/// Entries other than files and directories (like symbolic links or
+/// submodules) are ignored.
+pub(crate) fn read<T: DeserializeOwned>(repository: &Repository, tree: Oid, options: &DeserializerOptions) -> Result<T, de::Error> {
+    let fs = Arc::new(MemFs::new());
+    let tree = repository.find_tree(tree).map_err(git_error)?;
+    read_tree(repository, &tree, &fs, Path::new(""))?;
+    T::deserialize(FilesystemDeserializer::with_backend(Path::new(""), options.clone(), fs))
+}
+
+fn read_tree(repository: &Repository, tree: &Tree, fs: &MemFs, path: &Path) -> Result<(), io::Error> {
+    for entry in tree.iter() {
+        let name = match entry.name() {
+            Some(name) => name,
+            None => continue,
+        };
+        let entry_path = path.join(name);
+        match entry.kind() {
+            Some(ObjectType::Tree) => {
+                fs.create_dir(&entry_path)?;
+                let subtree = repository.find_tree(entry.id()).map_err(git_error)?;
+                read_tree(repository, &subtree, fs, &entry_path)?;
+            },
+            Some(ObjectType::Blob) if entry.filemode() == FILE_MODE || entry.filemode() == EXECUTABLE_MODE => {
+                let blob = repository.find_blob(entry.id()).map_err(git_error)?;
+                fs.write(&entry_path, blob.content())?;
+            },
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Resolves a revision (like `main`, a commit id or `HEAD:config`) to a tree id
+pub(crate) fn resolve(repository: &Repository, revision: &str) -> Result<Oid, de::Error> {
+    let object = repository.revparse_single(revision).map_err(git_error)?;
+    Ok(object.peel_to_tree().map_err(git_error)?.id())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use git2::Signature;
+    use tempdir::TempDir;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Mode {
+        Plain,
+        Tls { cert: String },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Server {
+        host: String,
+        ports: Vec<u16>,
+        mode: Mode,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Config {
+        servers: Vec<Server>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let repository = Repository::init_bare(tmp.path()).unwrap();
+        let config = Config { servers: vec![
+            Server { host: "localhost".into(), ports: vec![80], mode: Mode::Plain },
+            Server { host: "example.com".into(), ports: vec![], mode: Mode::Tls { cert: "cert.pem".into() } },
+        ] };
+        let id = write(&repository, &config, &SerializerOptions::new()).unwrap();
+        let tree = repository.find_tree(id).unwrap();
+        let entry = tree.get_path(Path::new("servers/1/mode/variant")).unwrap();
+        assert_eq!(repository.find_blob(entry.id()).unwrap().content(), b"Tls");
+        assert_eq!(entry.filemode(), FILE_MODE);
+        assert_eq!(read::<Config>(&repository, id, &DeserializerOptions::new()).unwrap(), config);
+        // Same value, same tree
+        assert_eq!(write(&repository, &config, &SerializerOptions::new()).unwrap(), id);
+
+        let signature = Signature::now("serde_fs", "serde_fs@example.com").unwrap();
+        repository.commit(Some("refs/heads/main"), &signature, &signature, "Configuration", &tree, &[]).unwrap();
+        assert_eq!(resolve(&repository, "main").unwrap(), id);
+        let server_id = resolve(&repository, "main:servers/0").unwrap();
+        assert_eq!(read::<Server>(&repository, server_id, &DeserializerOptions::new()).unwrap(), config.servers[0]);
+        assert!(resolve(&repository, "missing").is_err());
+    }
+
+    #[test]
+    fn executable() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let repository = Repository::init_bare(tmp.path()).unwrap();
+        let id = write(&repository, &vec!["#!/bin/sh"], &SerializerOptions::new().file_mode(0o755)).unwrap();
+        let tree = repository.find_tree(id).unwrap();
+        assert_eq!(tree.get_name("0").unwrap().filemode(), EXECUTABLE_MODE);
+    }
+
+    #[test]
+    fn scalar() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let repository = Repository::init_bare(tmp.path()).unwrap();
+        match write(&repository, &80u16, &SerializerOptions::new()) {
+            Err(ser::Error::IoError(ref err)) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+            result => panic!("unexpected {:?}", result),
+        }
+        assert!(write(&repository, &Option::<u16>::None, &SerializerOptions::new()).is_err());
+    }
+
+}
@@ -0,0 +1,138 @@
+//! Mapping between map keys and file names
+
+use std::fmt::Debug;
+
+/// Reversible mapping between map (and struct) keys and file names
+///
+/// `decode` must reverse `encode`. It is also given file names that were
+/// not produced by `encode` (for example, auxiliary files), and may reject
+/// them by returning `None`, in which case the deserializer skips them.
+pub trait KeyCodec: Debug + Send + Sync {
+    fn encode(&self, key: &str) -> String;
+    fn decode(&self, name: &str) -> Option<String>;
+}
+
+/// Uses keys as file names verbatim (default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Verbatim;
+
+impl KeyCodec for Verbatim {
+    fn encode(&self, key: &str) -> String {
+        String::from(key)
+    }
+
+    fn decode(&self, name: &str) -> Option<String> {
+        Some(String::from(name))
+    }
+}
+
+/// Percent-encodes characters that can't be used in file names
+///
+/// Encodes `%`, path separators, control characters, characters reserved on
+/// Windows (`<>:"|?*`) and a leading dot (so keys like `..` or `.git`
+/// can't clash with special or hidden files). The empty key is encoded as `%`.
+///
+/// Decoding only accepts names `encode` could have produced, so every file
+/// name maps to at most one key.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PercentEncoding;
+
+impl PercentEncoding {
+    fn needs_encoding(c: char, first: bool) -> bool {
+        match c {
+            '%' | '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*' => true,
+            '.' => first,
+            c => c.is_control(),
+        }
+    }
+
+    // Only the uppercase digits `encode` produces are accepted
+    fn hex_digit(b: u8) -> Option<u8> {
+        match b {
+            b'0'..=b'9' => Some(b - b'0'),
+            b'A'..=b'F' => Some(b - b'A' + 10),
+            _ => None,
+        }
+    }
+}
+
+impl KeyCodec for PercentEncoding {
+    fn encode(&self, key: &str) -> String {
+        if key.is_empty() {
+            return String::from("%");
+        }
+        let mut name = String::with_capacity(key.len());
+        for (i, c) in key.chars().enumerate() {
+            if PercentEncoding::needs_encoding(c, i == 0) {
+                let mut buf = [0; 4];
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    name.push_str(&format!("%{:02X}", b));
+                }
+            } else {
+                name.push(c);
+            }
+        }
+        name
+    }
+
+    fn decode(&self, name: &str) -> Option<String> {
+        if name == "%" {
+            return Some(String::new());
+        }
+        let bytes = name.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' {
+                let hi = bytes.get(i + 1).and_then(|&b| PercentEncoding::hex_digit(b))?;
+                let lo = bytes.get(i + 2).and_then(|&b| PercentEncoding::hex_digit(b))?;
+                decoded.push(hi << 4 | lo);
+                i += 3;
+            } else {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+        let key = String::from_utf8(decoded).ok()?;
+        if self.encode(&key) == name { Some(key) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn percent_encoding() {
+        let codec = PercentEncoding;
+        assert_eq!(codec.encode("a/b"), "a%2Fb");
+        assert_eq!(codec.encode(".."), "%2E.");
+        assert_eq!(codec.encode(".git"), "%2Egit");
+        assert_eq!(codec.encode("a.b"), "a.b");
+        assert_eq!(codec.encode("100%"), "100%25");
+        assert_eq!(codec.encode("a\0b"), "a%00b");
+        assert_eq!(codec.encode("über"), "über");
+        assert_eq!(codec.encode(""), "%");
+        assert_eq!(codec.encode("."), "%2E");
+        for key in &["", "a/b", "..", ".", ".git", "a.b", "100%", "a\0b", "\\", "über/\u{1}"] {
+            assert_eq!(codec.decode(&codec.encode(key)).unwrap(), *key);
+        }
+    }
+
+    #[test]
+    fn percent_decoding_invalid() {
+        let codec = PercentEncoding;
+        assert!(codec.decode("%2").is_none());
+        assert!(codec.decode("%+1").is_none());
+        assert!(codec.decode("%2f").is_none());
+        assert!(codec.decode("%41").is_none());
+        assert!(codec.decode("%%").is_none());
+        assert!(codec.decode(".").is_none());
+        assert!(codec.decode("..").is_none());
+        assert!(codec.decode("%zz").is_none());
+        assert!(codec.decode("%FF").is_none());
+        assert_eq!(codec.decode("README.md").unwrap(), "README.md");
+    }
+
+}
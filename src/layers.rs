@@ -0,0 +1,187 @@
+//! Merging of trees layered on top of each other
+
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use de::{Error, DeserializerOptions, FilesystemDeserializer, SeqLayering};
+use value::{self, Value};
+
+/// Reads and merges layers, from the lowest to the highest priority
+pub(crate) fn read<P: AsRef<Path>>(layers: &[P], options: &DeserializerOptions) -> Result<Value, Error> {
+    let mut tree = Value::Missing;
+    for layer in layers {
+        tree = merge(tree, value::read(layer)?, options);
+    }
+    Ok(tree)
+}
+
+/// Creates a deserializer of merged layers
+pub(crate) fn deserializer<P: AsRef<Path>>(layers: &[P], options: &DeserializerOptions) -> Result<FilesystemDeserializer<PathBuf>, Error> {
+    let tree = read(layers, options)?;
+    Ok(FilesystemDeserializer::with_backend(PathBuf::new(), options.clone(), Arc::new(tree)))
+}
+
+/// Puts `upper` on top of `lower`
+///
+/// Directories are merged entry by entry, sequences according to the
+/// `SeqLayering` policy, anything else in `upper` replaces what's in `lower`.
+fn merge(lower: Value, upper: Value, options: &DeserializerOptions) -> Value {
+    match (lower, upper) {
+        (lower, Value::Missing) => lower,
+        (lower, Value::Dir(upper)) => {
+            let mut from_seq = false;
+            let mut merged = match lower {
+                Value::Dir(map) => map,
+                Value::Seq(seq) if upper.is_empty() && options.seq_layering == SeqLayering::Append =>
+                    return Value::Seq(seq),
+                // Elements of the upper layer that don't start at `0` (or leave
+                // gaps) make it a directory, so it is merged by index
+                Value::Seq(seq) if options.seq_layering == SeqLayering::Merge => {
+                    from_seq = true;
+                    seq.into_iter().enumerate().map(|(index, value)| (index.to_string(), value)).collect()
+                },
+                _ => BTreeMap::new(),
+            };
+            let (markers, entries): (Vec<_>, Vec<_>) = upper.into_iter()
+                .partition(|entry| !options.deletion_marker.is_empty() && entry.0.starts_with(&options.deletion_marker));
+            for (marker, _) in markers {
+                merged.remove(&marker[options.deletion_marker.len()..]);
+            }
+            for (name, value) in entries {
+                let lower = merged.remove(&name).unwrap_or_default();
+                merged.insert(name, merge(lower, value, options));
+            }
+            if from_seq {
+                // Deleted elements leave gaps, which are closed by shifting the
+                // elements following them
+                let mut indices = vec![];
+                for name in merged.keys() {
+                    match name.parse::<usize>() {
+                        Ok(index) if *name == index.to_string() => indices.push((index, name.clone())),
+                        _ => return Value::Dir(merged),
+                    }
+                }
+                indices.sort();
+                return Value::Seq(indices.into_iter().filter_map(|(_, name)| merged.remove(&name)).collect());
+            }
+            Value::Dir(merged)
+        },
+        (lower, Value::Seq(upper)) => {
+            // Elements are still merged with nothing to strip deletion markers
+            let upper = upper.into_iter().map(|value| merge(Value::Missing, value, options));
+            match lower {
+                Value::Seq(mut seq) if options.seq_layering == SeqLayering::Append => {
+                    seq.extend(upper);
+                    Value::Seq(seq)
+                },
+                Value::Seq(seq) if options.seq_layering == SeqLayering::Merge => {
+                    let mut lower = seq.into_iter();
+                    let mut merged: Vec<_> = upper.map(|value| merge(lower.next().unwrap_or_default(), value, options)).collect();
+                    merged.extend(lower);
+                    Value::Seq(merged)
+                },
+                _ => Value::Seq(upper.collect()),
+            }
+        },
+        (_, upper) => upper,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde::Deserialize;
+    use tempdir::TempDir;
+
+    fn dir(entries: Vec<(&str, Value)>) -> Value {
+        Value::Dir(entries.into_iter().map(|(name, value)| (String::from(name), value)).collect())
+    }
+
+    fn seq(items: Vec<&str>) -> Value {
+        Value::Seq(items.into_iter().map(Value::from).collect())
+    }
+
+    #[test]
+    fn dirs() {
+        let options = DeserializerOptions::new();
+        let lower = dir(vec![("a", Value::from("1")), ("b", dir(vec![("c", Value::from("2")), ("d", Value::from("3"))]))]);
+        let upper = dir(vec![("b", dir(vec![("d", Value::from("4"))])), ("e", Value::from("5"))]);
+        assert_eq!(merge(lower, upper, &options),
+                   dir(vec![("a", Value::from("1")), ("b", dir(vec![("c", Value::from("2")), ("d", Value::from("4"))])),
+                            ("e", Value::from("5"))]));
+        assert_eq!(merge(dir(vec![("a", Value::from("1"))]), Value::from("2"), &options), Value::from("2"));
+        assert_eq!(merge(Value::from("2"), dir(vec![("a", Value::from("1"))]), &options), dir(vec![("a", Value::from("1"))]));
+    }
+
+    #[test]
+    fn deletions() {
+        let options = DeserializerOptions::new();
+        let lower = dir(vec![("a", Value::from("1")), ("b", dir(vec![("c", Value::from("2"))]))]);
+        let upper = dir(vec![(".deleted.a", Value::from("")), (".deleted.b", Value::from("")),
+                             ("b", dir(vec![("d", Value::from("3"))]))]);
+        assert_eq!(merge(lower.clone(), upper, &options), dir(vec![("b", dir(vec![("d", Value::from("3"))]))]));
+        let options = DeserializerOptions::new().deletion_marker("-");
+        assert_eq!(merge(lower, dir(vec![("-a", Value::from(""))]), &options),
+                   dir(vec![("b", dir(vec![("c", Value::from("2"))]))]));
+    }
+
+    #[test]
+    fn seqs() {
+        let options = DeserializerOptions::new();
+        assert_eq!(merge(seq(vec!["1", "2"]), seq(vec!["3"]), &options), seq(vec!["3", "2"]));
+        assert_eq!(merge(seq(vec!["1"]), seq(vec!["3", "4"]), &options), seq(vec!["3", "4"]));
+        assert_eq!(merge(seq(vec!["1", "2"]), dir(vec![("1", Value::from("3"))]), &options), seq(vec!["1", "3"]));
+        assert_eq!(merge(seq(vec!["1", "2", "3"]), dir(vec![(".deleted.0", Value::from(""))]), &options), seq(vec!["2", "3"]));
+        assert_eq!(merge(seq(vec!["1", "2"]), dir(vec![("a", Value::from("3"))]), &options),
+                   dir(vec![("0", Value::from("1")), ("1", Value::from("2")), ("a", Value::from("3"))]));
+        assert_eq!(merge(seq(vec!["1", "2"]), dir(vec![]), &options), seq(vec!["1", "2"]));
+        let servers = Value::Seq(vec![dir(vec![("host", Value::from("a")), ("port", Value::from("80"))]),
+                                      dir(vec![("host", Value::from("b")), ("port", Value::from("81"))])]);
+        let local = Value::Seq(vec![dir(vec![("port", Value::from("8080"))])]);
+        assert_eq!(merge(servers, local, &options),
+                   Value::Seq(vec![dir(vec![("host", Value::from("a")), ("port", Value::from("8080"))]),
+                                   dir(vec![("host", Value::from("b")), ("port", Value::from("81"))])]));
+        let options = DeserializerOptions::new().seq_layering(SeqLayering::Replace);
+        assert_eq!(merge(seq(vec!["1", "2"]), seq(vec!["3"]), &options), seq(vec!["3"]));
+        assert_eq!(merge(seq(vec!["1", "2"]), dir(vec![]), &options), dir(vec![]));
+        let options = DeserializerOptions::new().seq_layering(SeqLayering::Append);
+        assert_eq!(merge(seq(vec!["1", "2"]), seq(vec!["3"]), &options), seq(vec!["1", "2", "3"]));
+        assert_eq!(merge(seq(vec!["1", "2"]), dir(vec![]), &options), seq(vec!["1", "2"]));
+        assert_eq!(merge(dir(vec![]), seq(vec!["3"]), &options), seq(vec!["3"]));
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Server {
+        host: String,
+        port: u16,
+        tags: Vec<String>,
+        backup: Option<String>,
+    }
+
+    #[test]
+    fn deserialize() {
+        use std::fs;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let (base, local) = (tmp.path().join("base"), tmp.path().join("local"));
+        fs::create_dir_all(base.join("tags")).unwrap();
+        fs::create_dir_all(local.join("tags")).unwrap();
+        fs::write(base.join("host"), "localhost").unwrap();
+        fs::write(base.join("port"), "80").unwrap();
+        fs::write(base.join("backup"), "backup.local").unwrap();
+        fs::write(base.join("tags").join("0"), "web").unwrap();
+        fs::write(local.join("port"), "8080").unwrap();
+        fs::write(local.join(".deleted.backup"), "").unwrap();
+        fs::write(local.join("tags").join("0"), "dev").unwrap();
+        let layers = [&base, &tmp.path().join("missing"), &local];
+
+        let server = Server::deserialize(deserializer(&layers, &DeserializerOptions::new()).unwrap()).unwrap();
+        assert_eq!(server, Server { host: "localhost".into(), port: 8080, tags: vec!["dev".into()], backup: None });
+
+        let options = DeserializerOptions::new().seq_layering(SeqLayering::Append);
+        let server = Server::deserialize(deserializer(&layers, &options).unwrap()).unwrap();
+        assert_eq!(server.tags, vec![String::from("web"), String::from("dev")]);
+    }
+
+}
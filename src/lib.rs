@@ -1,20 +1,42 @@
 //! # Serde Serialization/deserialization to and from the file system
 //!
-extern crate serde;
+// The code style predates these lints
+#![allow(clippy::multiple_bound_locations, clippy::op_ref, clippy::extra_unused_lifetimes)]
+#![cfg_attr(test, allow(clippy::bool_assert_comparison))]
+#[macro_use] extern crate serde;
 #[cfg(test)] #[macro_use] extern crate serde_derive;
 #[cfg(test)] extern crate serde_bytes;
+#[cfg(test)] #[macro_use] extern crate serde_json;
 #[macro_use] extern crate derive_error;
+#[cfg(feature = "watch")] extern crate notify;
+#[cfg(feature = "async")] extern crate tokio;
+#[cfg(feature = "tar")] extern crate tar;
+#[cfg(feature = "zip")] extern crate zip;
+#[cfg(feature = "git2")] extern crate git2;
 
 #[cfg(test)] extern crate tempdir;
 #[cfg(test)] #[macro_use] extern crate assert_matches;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 
+mod confine;
+mod backend;
+pub use backend::{Backend, Kind, StdFs};
+mod mem;
+pub use mem::MemFs;
+pub mod pointer;
+mod key;
+pub use key::{KeyCodec, Verbatim, PercentEncoding};
+
 mod ser;
 pub use ser::FilesystemSerializer;
+pub use ser::SerializerOptions;
+pub use ser::Durability;
+pub use ser::Prune;
 pub use ser::Error as SerializerError;
 
 /// Serializes a value to a file system
@@ -41,18 +63,460 @@ pub use ser::Error as SerializerError;
 /// }
 ///
 /// ```
-pub fn to_fs<T: ?Sized, P>(path: P, value: &T) -> Result<(), SerializerError>
-    where T: Serialize, P: AsRef<Path> {
+pub fn to_fs<T: ?Sized + Serialize, P: AsRef<Path>>(path: P, value: &T) -> Result<(), SerializerError> {
     let serializer = FilesystemSerializer::new(path);
     value.serialize(serializer)
 }
 
+mod atomic;
+
+/// Serializes a value to a file system atomically
+///
+/// The value is serialized into a staging directory next to `path` (seeded with
+/// the current contents of `path`) and then swapped in with a pair of renames
+/// (moving the current tree aside first), so readers never observe a partially
+/// written tree. They might, however, briefly find nothing at `path` between
+/// the two renames. If serialization fails, `path` is left untouched.
+///
+/// ```
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// struct MyStruct {
+///   value: String,
+/// }
+///
+/// extern crate serde_fs;
+/// use serde_fs::{from_fs, to_fs_atomic};
+///
+/// fn main() {
+///   let val = MyStruct { value: "Hello".into() };
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   let path = tmp.path().join("config");
+///   to_fs_atomic(&path, &val).unwrap();
+///   let val1: MyStruct = from_fs(&path).unwrap();
+///   assert_eq!(val1, val);
+/// }
+///
+/// ```
+pub fn to_fs_atomic<T: ?Sized + Serialize, P: AsRef<Path>>(path: P, value: &T) -> Result<(), SerializerError> {
+    to_fs_with(path, value, &SerializerOptions::new().atomic(true))
+}
+
+/// Serializes a value to a file system using given options
+///
+/// ```
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// struct MyStruct {
+///   value: String,
+/// }
+///
+/// extern crate serde_fs;
+/// use serde_fs::{from_fs_with, to_fs_with, SerializerOptions, DeserializerOptions, Durability};
+///
+/// fn main() {
+///   let val = MyStruct { value: "Hello".into() };
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   let options = SerializerOptions::new().durability(Durability::Rename).trailing_newline(true);
+///   to_fs_with(tmp.path(), &val, &options).unwrap();
+///   let val1: MyStruct = from_fs_with(tmp.path(), &DeserializerOptions::new().trailing_newline(true)).unwrap();
+///   assert_eq!(val1, val);
+/// }
+///
+/// ```
+pub fn to_fs_with<T: ?Sized + Serialize, P: AsRef<Path>>(path: P, value: &T, options: &SerializerOptions) -> Result<(), SerializerError> {
+    to_fs_changes(path, value, options).map(|_| ())
+}
+
+/// Serializes a value to a file system using given options, reporting what changed
+///
+/// Returns paths (relative to `path`) that were written, created or removed.
+/// Combined with `SerializerOptions::skip_unchanged`, serializing the same
+/// value twice reports no changes the second time.
+///
+/// ```
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize)]
+/// struct MyStruct {
+///   value: String,
+/// }
+///
+/// extern crate serde_fs;
+/// use std::path::PathBuf;
+/// use serde_fs::{to_fs_changes, SerializerOptions};
+///
+/// fn main() {
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   let options = SerializerOptions::new().skip_unchanged(true);
+///   let changes = to_fs_changes(tmp.path(), &MyStruct { value: "Hello".into() }, &options).unwrap();
+///   assert_eq!(changes, vec![PathBuf::from("value")]);
+///   let changes = to_fs_changes(tmp.path(), &MyStruct { value: "Hello".into() }, &options).unwrap();
+///   assert!(changes.is_empty());
+/// }
+///
+/// ```
+pub fn to_fs_changes<T: ?Sized + Serialize, P: AsRef<Path>>(path: P, value: &T, options: &SerializerOptions) -> Result<Vec<PathBuf>, SerializerError> {
+    let mut changes = vec![];
+    {
+        let mut serialize = |path: &Path| {
+            let serializer = FilesystemSerializer::with_options(path, options.clone());
+            value.serialize(serializer.clone())?;
+            changes = serializer.changes();
+            Ok(())
+        };
+        if options.is_atomic() {
+            atomic::replace(path, serialize)?;
+        } else {
+            serialize(path.as_ref())?;
+        }
+    }
+    Ok(changes)
+}
+
+mod plan;
+pub use plan::{Plan, Operation};
+
+/// Plans serializing a value to a file system without modifying it
+///
+/// The returned `Plan` lists the operations serialization would perform
+/// (given the tree's current state) and can be applied later.
+///
+/// ```
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize)]
+/// struct MyStruct {
+///   value: String,
+/// }
+///
+/// extern crate serde_fs;
+/// use std::path::PathBuf;
+/// use serde_fs::{plan, Operation};
+///
+/// fn main() {
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   let plan = plan(tmp.path(), &MyStruct { value: "Hello".into() }).unwrap();
+///   assert_eq!(plan.operations(), &[Operation::CreateFile { path: PathBuf::from("value"), content: b"Hello".to_vec() }]);
+///   assert!(!tmp.path().join("value").exists());
+///   plan.apply().unwrap();
+///   assert!(tmp.path().join("value").exists());
+/// }
+///
+/// ```
+pub fn plan<T: ?Sized + Serialize, P: AsRef<Path>>(path: P, value: &T) -> Result<Plan, SerializerError> {
+    plan_with(path, value, &SerializerOptions::default())
+}
+
+/// Plans serializing a value to a file system using given options
+///
+/// Atomic replacement can't be planned, `SerializerOptions::atomic` is ignored
+/// (and so is it when the plan is applied).
+pub fn plan_with<T: ?Sized + Serialize, P: AsRef<Path>>(path: P, value: &T, options: &SerializerOptions) -> Result<Plan, SerializerError> {
+    plan::plan(path, value, options)
+}
+
+/// Applies a plan
+pub fn apply(plan: &Plan) -> Result<(), SerializerError> {
+    plan.apply()
+}
+
+mod diff;
+pub use diff::Change;
+pub use diff::Error as DiffError;
+
+/// Compares two trees
+///
+/// Returns the subtrees that were added, removed or changed going from
+/// `old` to `new` (see `Value::diff`).
+///
+/// ```
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+///
+/// extern crate serde_fs;
+/// use serde_fs::{diff, to_fs, Change, Value};
+///
+/// fn main() {
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   to_fs(tmp.path().join("old"), &vec![80, 443]).unwrap();
+///   to_fs(tmp.path().join("new"), &vec![8080, 443]).unwrap();
+///   let changes = diff(tmp.path().join("old"), tmp.path().join("new")).unwrap();
+///   assert_eq!(changes, vec![Change::Changed { pointer: "0".into(), old: Value::from("80"), new: Value::from("8080") }]);
+/// }
+///
+/// ```
+pub fn diff<P: AsRef<Path>, Q: AsRef<Path>>(old: P, new: Q) -> Result<Vec<Change>, DeserializerError> {
+    Ok(from_fs_value(old)?.diff(&from_fs_value(new)?))
+}
+
+/// Compares a tree with a value
+///
+/// Returns the subtrees serializing `value` to `path` would add, remove or
+/// change. Entries the serializer would leave alone (like auxiliary files,
+/// unless `SerializerOptions::prune` removes them) aren't reported.
+///
+/// ```
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize)]
+/// struct MyStruct {
+///   value: String,
+/// }
+///
+/// extern crate serde_fs;
+/// use serde_fs::{diff_value, to_fs, Change};
+///
+/// fn main() {
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   to_fs(tmp.path(), &MyStruct { value: "Hello".into() }).unwrap();
+///   let changes = diff_value(tmp.path(), &MyStruct { value: "Bye".into() }).unwrap();
+///   assert_eq!(changes.len(), 1);
+///   assert_eq!(changes[0].pointer(), "value");
+/// }
+///
+/// ```
+pub fn diff_value<T: ?Sized + Serialize, P: AsRef<Path>>(path: P, value: &T) -> Result<Vec<Change>, DiffError> {
+    diff_value_with(path, value, &SerializerOptions::default())
+}
+
+/// Compares a tree with a value serialized using given options
+pub fn diff_value_with<T: ?Sized + Serialize, P: AsRef<Path>>(path: P, value: &T, options: &SerializerOptions) -> Result<Vec<Change>, DiffError> {
+    diff::diff_value(path, value, options)
+}
+
+mod env;
+pub use env::EnvOverrides;
+
+/// Serializes a value to a backend (see `MemFs`)
+pub fn to_backend<T: ?Sized + Serialize, P: AsRef<Path>>(backend: Arc<dyn Backend>, path: P, value: &T) -> Result<(), SerializerError> {
+    to_backend_with(backend, path, value, &SerializerOptions::default())
+}
+
+/// Serializes a value to a backend using given options
+///
+/// `SerializerOptions::atomic` is not supported by backends.
+pub fn to_backend_with<T: ?Sized + Serialize, P: AsRef<Path>>(backend: Arc<dyn Backend>, path: P, value: &T, options: &SerializerOptions) -> Result<(), SerializerError> {
+    value.serialize(FilesystemSerializer::with_backend(path, options.clone(), backend))
+}
+
+#[cfg(feature = "tar")]
+mod tar_archive;
+
+/// Serializes a value into a tar archive
+///
+/// The tree is appended to `builder` under `path`, with the same layout
+/// `to_fs` would produce in a directory at `path`.
+///
+/// ```
+/// extern crate tar;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// struct MyStruct {
+///   value: String,
+/// }
+///
+/// extern crate serde_fs;
+/// use serde_fs::{from_tar, to_tar};
+///
+/// fn main() {
+///   let val = MyStruct { value: "Hello".into() };
+///   let mut builder = tar::Builder::new(vec![]);
+///   to_tar(&mut builder, "config", &val).unwrap();
+///   let archive = builder.into_inner().unwrap();
+///   let val1: MyStruct = from_tar(&archive[..], "config").unwrap();
+///   assert_eq!(val1, val);
+/// }
+///
+/// ```
+#[cfg(feature = "tar")]
+pub fn to_tar<W: ::std::io::Write, T: ?Sized + Serialize, P: AsRef<Path>>(builder: &mut tar::Builder<W>, path: P, value: &T) -> Result<(), SerializerError> {
+    to_tar_with(builder, path, value, &SerializerOptions::default())
+}
+
+/// Serializes a value into a tar archive using given options
+///
+/// File and directory modes of the options apply to archive entries.
+#[cfg(feature = "tar")]
+pub fn to_tar_with<W: ::std::io::Write, T: ?Sized + Serialize, P: AsRef<Path>>(builder: &mut tar::Builder<W>, path: P, value: &T, options: &SerializerOptions) -> Result<(), SerializerError> {
+    tar_archive::write(builder, path, value, options)
+}
+
+/// Deserializes an instance of type `T` from the tree at `path` in a tar stream
+#[cfg(feature = "tar")]
+pub fn from_tar<R: ::std::io::Read, P: AsRef<Path>, T: DeserializeOwned>(reader: R, path: P) -> Result<T, DeserializerError> {
+    from_tar_with(reader, path, &DeserializerOptions::default())
+}
+
+/// Deserializes an instance of type `T` from a tar stream using given options
+#[cfg(feature = "tar")]
+pub fn from_tar_with<R: ::std::io::Read, P: AsRef<Path>, T: DeserializeOwned>(reader: R, path: P, options: &DeserializerOptions) -> Result<T, DeserializerError> {
+    tar_archive::read(reader, path, options)
+}
+
+#[cfg(feature = "zip")]
+mod zip_archive;
+
+/// Serializes a value into a zip archive
+///
+/// The tree is added to `writer` under `path`, with the same layout `to_fs`
+/// would produce in a directory at `path`, so an archive and the directory
+/// it was extracted to can be used interchangeably.
+///
+/// ```
+/// extern crate zip;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// struct MyStruct {
+///   value: String,
+/// }
+///
+/// extern crate serde_fs;
+/// use std::io::Cursor;
+/// use serde_fs::{from_zip, to_zip};
+///
+/// fn main() {
+///   let val = MyStruct { value: "Hello".into() };
+///   let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+///   to_zip(&mut writer, "config", &val).unwrap();
+///   let archive = writer.finish().unwrap();
+///   let val1: MyStruct = from_zip(archive, "config").unwrap();
+///   assert_eq!(val1, val);
+/// }
+///
+/// ```
+#[cfg(feature = "zip")]
+pub fn to_zip<W: ::std::io::Write + ::std::io::Seek, T: ?Sized + Serialize, P: AsRef<Path>>(writer: &mut zip::ZipWriter<W>, path: P, value: &T) -> Result<(), SerializerError> {
+    to_zip_with(writer, path, value, &SerializerOptions::default())
+}
+
+/// Serializes a value into a zip archive using given options
+///
+/// File and directory modes of the options apply to archive entries.
+#[cfg(feature = "zip")]
+pub fn to_zip_with<W: ::std::io::Write + ::std::io::Seek, T: ?Sized + Serialize, P: AsRef<Path>>(writer: &mut zip::ZipWriter<W>, path: P, value: &T, options: &SerializerOptions) -> Result<(), SerializerError> {
+    zip_archive::write(writer, path, value, options)
+}
+
+/// Deserializes an instance of type `T` from the tree at `path` in a zip archive
+#[cfg(feature = "zip")]
+pub fn from_zip<R: ::std::io::Read + ::std::io::Seek, P: AsRef<Path>, T: DeserializeOwned>(reader: R, path: P) -> Result<T, DeserializerError> {
+    from_zip_with(reader, path, &DeserializerOptions::default())
+}
+
+/// Deserializes an instance of type `T` from a zip archive using given options
+#[cfg(feature = "zip")]
+pub fn from_zip_with<R: ::std::io::Read + ::std::io::Seek, P: AsRef<Path>, T: DeserializeOwned>(reader: R, path: P, options: &DeserializerOptions) -> Result<T, DeserializerError> {
+    zip_archive::read(reader, path, options)
+}
+
+#[cfg(feature = "git2")]
+mod git_tree;
+
+/// Serializes a value into a git repository as a tree, returning the tree's id
+///
+/// Only blob and tree objects are written (there's no need for a working
+/// copy, and the repository can be bare), with the same layout `to_fs` would
+/// produce in a directory. The value has to serialize to a directory (like
+/// a struct, a map or a sequence).
+///
+/// ```
+/// extern crate git2;
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// struct MyStruct {
+///   value: String,
+/// }
+///
+/// extern crate serde_fs;
+/// use serde_fs::{from_git, from_git_rev, to_git};
+///
+/// fn main() {
+///   let val = MyStruct { value: "Hello".into() };
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   let repository = git2::Repository::init_bare(tmp.path()).unwrap();
+///   let tree = to_git(&repository, &val).unwrap();
+///   let val1: MyStruct = from_git(&repository, tree).unwrap();
+///   assert_eq!(val1, val);
+///
+///   let signature = git2::Signature::now("serde_fs", "serde_fs@example.com").unwrap();
+///   let tree = repository.find_tree(tree).unwrap();
+///   repository.commit(Some("HEAD"), &signature, &signature, "Configuration", &tree, &[]).unwrap();
+///   let val2: MyStruct = from_git_rev(&repository, "HEAD").unwrap();
+///   assert_eq!(val2, val);
+/// }
+///
+/// ```
+#[cfg(feature = "git2")]
+pub fn to_git<T: ?Sized + Serialize>(repository: &git2::Repository, value: &T) -> Result<git2::Oid, SerializerError> {
+    to_git_with(repository, value, &SerializerOptions::default())
+}
+
+/// Serializes a value into a git repository as a tree using given options
+///
+/// Files are marked executable if `SerializerOptions::file_mode` allows
+/// executing them.
+#[cfg(feature = "git2")]
+pub fn to_git_with<T: ?Sized + Serialize>(repository: &git2::Repository, value: &T, options: &SerializerOptions) -> Result<git2::Oid, SerializerError> {
+    git_tree::write(repository, value, options)
+}
+
+/// Deserializes an instance of type `T` from a tree of a git repository
+#[cfg(feature = "git2")]
+pub fn from_git<T: DeserializeOwned>(repository: &git2::Repository, tree: git2::Oid) -> Result<T, DeserializerError> {
+    from_git_with(repository, tree, &DeserializerOptions::default())
+}
+
+/// Deserializes an instance of type `T` from a tree of a git repository using given options
+#[cfg(feature = "git2")]
+pub fn from_git_with<T: DeserializeOwned>(repository: &git2::Repository, tree: git2::Oid, options: &DeserializerOptions) -> Result<T, DeserializerError> {
+    git_tree::read(repository, tree, options)
+}
+
+/// Deserializes an instance of type `T` from a revision of a git repository
+///
+/// Revisions are anything `git rev-parse` understands that leads to a
+/// tree: a reference (`main`), a commit id, or a subtree of either
+/// (`main:config`).
+#[cfg(feature = "git2")]
+pub fn from_git_rev<T: DeserializeOwned>(repository: &git2::Repository, revision: &str) -> Result<T, DeserializerError> {
+    from_git_rev_with(repository, revision, &DeserializerOptions::default())
+}
+
+/// Deserializes an instance of type `T` from a revision of a git repository using given options
+#[cfg(feature = "git2")]
+pub fn from_git_rev_with<T: DeserializeOwned>(repository: &git2::Repository, revision: &str, options: &DeserializerOptions) -> Result<T, DeserializerError> {
+    from_git_with(repository, git_tree::resolve(repository, revision)?, options)
+}
+
 mod de;
 pub use de::FilesystemDeserializer;
+pub use de::DeserializerOptions;
 pub use de::Error as DeserializerError;
+pub use de::SeqLayering;
 
 /// Deserializes a value from a file system
 ///
+/// Leaf files seen through internally tagged or untagged enums are typed by
+/// their contents (see `DeserializerOptions::infer_types`).
+///
 /// ```
 /// extern crate tempdir;
 /// use tempdir::TempDir;
@@ -80,6 +544,34 @@ pub fn from_fs<P: AsRef<Path>, T: DeserializeOwned>(path: P) -> Result<T, Deseri
     T::deserialize(deserializer)
 }
 
+/// Deserializes a value from a file system using given options
+///
+/// ```
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// struct MyStruct {
+///   value: String,
+/// }
+///
+/// extern crate serde_fs;
+/// use serde_fs::{from_fs_with, DeserializerOptions};
+///
+/// fn main() {
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   std::fs::write(tmp.path().join("value"), "  Hello\n").unwrap();
+///   let val: MyStruct = from_fs_with(tmp.path(), &DeserializerOptions::new().trim(true)).unwrap();
+///   assert_eq!(val, MyStruct { value: "Hello".into() });
+/// }
+///
+/// ```
+pub fn from_fs_with<P: AsRef<Path>, T: DeserializeOwned>(path: P, options: &DeserializerOptions) -> Result<T, DeserializerError> {
+    let deserializer = FilesystemDeserializer::with_options(path, options.clone());
+    T::deserialize(deserializer)
+}
+
 /// Deserializes a value from a file system in place
 ///
 /// ```
@@ -109,3 +601,293 @@ pub fn from_fs_in_place<P: AsRef<Path>, T: DeserializeOwned>(path: P, place: &mu
     let deserializer = FilesystemDeserializer::new(path);
     T::deserialize_in_place(deserializer, place)
 }
+
+/// Deserializes an instance of type `T` from a backend (see `MemFs`)
+pub fn from_backend<P: AsRef<Path>, T: DeserializeOwned>(backend: Arc<dyn Backend>, path: P) -> Result<T, DeserializerError> {
+    from_backend_with(backend, path, &DeserializerOptions::default())
+}
+
+/// Deserializes an instance of type `T` from a backend using given options
+pub fn from_backend_with<P: AsRef<Path>, T: DeserializeOwned>(backend: Arc<dyn Backend>, path: P, options: &DeserializerOptions) -> Result<T, DeserializerError> {
+    T::deserialize(FilesystemDeserializer::with_backend(path, options.clone(), backend))
+}
+
+mod layers;
+
+/// Deserializes an instance of type `T` from layered file systems
+///
+/// Layers are listed from the lowest to the highest priority: each leaf is
+/// read from the highest-priority layer that has it, and directories as well
+/// as sequences are merged entry by entry (see `DeserializerOptions::seq_layering`
+/// for replacing or appending sequences instead).
+/// An empty file named `.deleted.name` in a layer hides `name` of the lower
+/// layers (see `DeserializerOptions::deletion_marker`). Layers that don't
+/// exist are skipped.
+///
+/// ```
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// struct Server {
+///   host: String,
+///   port: u16,
+/// }
+///
+/// extern crate serde_fs;
+/// use serde_fs::{from_fs_layers, to_fs, set};
+///
+/// fn main() {
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   let (base, local) = (tmp.path().join("base"), tmp.path().join("local"));
+///   to_fs(&base, &Server { host: "localhost".into(), port: 80 }).unwrap();
+///   set(&local, "port", &8080).unwrap();
+///   let server: Server = from_fs_layers(&[base, local]).unwrap();
+///   assert_eq!(server, Server { host: "localhost".into(), port: 8080 });
+/// }
+///
+/// ```
+pub fn from_fs_layers<P: AsRef<Path>, T: DeserializeOwned>(layers: &[P]) -> Result<T, DeserializerError> {
+    from_fs_layers_with(layers, &DeserializerOptions::default())
+}
+
+/// Deserializes an instance of type `T` from layered file systems using given options
+pub fn from_fs_layers_with<P: AsRef<Path>, T: DeserializeOwned>(layers: &[P], options: &DeserializerOptions) -> Result<T, DeserializerError> {
+    T::deserialize(layers::deserializer(layers, options)?)
+}
+
+#[cfg(feature = "async")]
+mod nonblocking;
+#[cfg(feature = "async")]
+pub use nonblocking::Blocking;
+
+/// Serializes a value to a file system without blocking the async runtime
+///
+/// Serialization runs on tokio's blocking thread pool (so it needs to own
+/// the value) once the returned future is polled within a tokio runtime.
+/// The on-disk mapping is the same as `to_fs`'s.
+///
+/// ```
+/// extern crate tempdir;
+/// extern crate tokio;
+/// use tempdir::TempDir;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+/// struct MyStruct {
+///   value: String,
+/// }
+///
+/// extern crate serde_fs;
+/// use serde_fs::{from_fs_async, to_fs_async};
+///
+/// fn main() {
+///   let val = MyStruct { value: "Hello".into() };
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+///   runtime.block_on(to_fs_async(tmp.path(), val.clone())).unwrap();
+///   let val1: MyStruct = runtime.block_on(from_fs_async(tmp.path())).unwrap();
+///   assert_eq!(val1, val);
+/// }
+///
+/// ```
+#[cfg(feature = "async")]
+pub fn to_fs_async<T: Serialize + Send + 'static, P: AsRef<Path>>(path: P, value: T) -> Blocking<(), SerializerError> {
+    to_fs_async_with(path, value, &SerializerOptions::default())
+}
+
+/// Serializes a value to a file system using given options without blocking the async runtime
+#[cfg(feature = "async")]
+pub fn to_fs_async_with<T: Serialize + Send + 'static, P: AsRef<Path>>(path: P, value: T, options: &SerializerOptions) -> Blocking<(), SerializerError> {
+    let (path, options) = (nonblocking::owned(path), options.clone());
+    Blocking::new(move || to_fs_with(path, &value, &options))
+}
+
+/// Deserializes an instance of type `T` from a file system without blocking the async runtime
+///
+/// See `to_fs_async`.
+#[cfg(feature = "async")]
+pub fn from_fs_async<P: AsRef<Path>, T: DeserializeOwned + Send + 'static>(path: P) -> Blocking<T, DeserializerError> {
+    from_fs_async_with(path, &DeserializerOptions::default())
+}
+
+/// Deserializes an instance of type `T` from a file system using given options without blocking the async runtime
+#[cfg(feature = "async")]
+pub fn from_fs_async_with<P: AsRef<Path>, T: DeserializeOwned + Send + 'static>(path: P, options: &DeserializerOptions) -> Blocking<T, DeserializerError> {
+    let (path, options) = (nonblocking::owned(path), options.clone());
+    Blocking::new(move || from_fs_with(path, &options))
+}
+
+#[cfg(feature = "watch")]
+mod watch;
+#[cfg(feature = "watch")]
+pub use watch::{Watcher, Reload, Error as WatchError};
+
+mod value;
+pub use value::Value;
+
+/// Reads a file system tree as a `Value`
+///
+/// File contents are read as is, directories with entries `0`..`n` are
+/// read as sequences and all other directories as maps.
+///
+/// ```
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+///
+/// extern crate serde_fs;
+/// use serde_fs::{from_fs_value, to_fs_value, Value};
+///
+/// fn main() {
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   std::fs::create_dir_all(tmp.path().join("servers").join("0")).unwrap();
+///   std::fs::write(tmp.path().join("servers").join("0").join("port"), "8080").unwrap();
+///   let mut value = from_fs_value(tmp.path()).unwrap();
+///   assert_eq!(value.at("servers/0/port").as_str(), Some("8080"));
+///   if let Value::Dir(ref mut map) = value {
+///     map.insert("name".into(), Value::from("web"));
+///   }
+///   to_fs_value(tmp.path(), &value).unwrap();
+///   assert_eq!(std::fs::read_to_string(tmp.path().join("name")).unwrap(), "web");
+/// }
+///
+/// ```
+pub fn from_fs_value<P: AsRef<Path>>(path: P) -> Result<Value, DeserializerError> {
+    value::read(path)
+}
+
+/// Writes a `Value` to a file system
+///
+/// `Value::Missing` removes whatever is at its path.
+pub fn to_fs_value<P: AsRef<Path>>(path: P, value: &Value) -> Result<(), SerializerError> {
+    to_fs(path, value)
+}
+
+/// Deserializes a subtree addressed by a pointer
+///
+/// Only the addressed subtree is read. If any of the pointer's segments
+/// doesn't exist, `DeserializerError::MissingSegment` reports the pointer
+/// up to that segment. See the `pointer` module for the syntax.
+///
+/// ```
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize)]
+/// struct Server {
+///   port: u16,
+/// }
+///
+/// #[derive(Serialize)]
+/// struct Config {
+///   servers: Vec<Server>,
+/// }
+///
+/// extern crate serde_fs;
+/// use serde_fs::{get, to_fs, DeserializerError};
+///
+/// fn main() {
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   to_fs(tmp.path(), &Config { servers: vec![Server { port: 8080 }] }).unwrap();
+///   let port: u16 = get(tmp.path(), "servers/0/port").unwrap();
+///   assert_eq!(port, 8080);
+///   match get::<_, u16>(tmp.path(), "servers/1/port") {
+///     Err(DeserializerError::MissingSegment(pointer)) => assert_eq!(pointer, "servers/1"),
+///     _ => unreachable!(),
+///   }
+/// }
+///
+/// ```
+pub fn get<P: AsRef<Path>, T: DeserializeOwned>(root: P, pointer: &str) -> Result<T, DeserializerError> {
+    get_with(root, pointer, &DeserializerOptions::default())
+}
+
+/// Deserializes a subtree addressed by a pointer using given options
+///
+/// Pointer segments are mapped to file names with the options' key codec.
+pub fn get_with<P: AsRef<Path>, T: DeserializeOwned>(root: P, pointer: &str, options: &DeserializerOptions) -> Result<T, DeserializerError> {
+    let segments = match pointer::parse(pointer) {
+        Some(segments) => segments,
+        None => return Err(DeserializerError::InvalidPointer(String::from(pointer))),
+    };
+    let deserializer = FilesystemDeserializer::with_options(root, options.clone()).descend(&segments)?;
+    T::deserialize(deserializer)
+}
+
+fn parse_pointer(pointer: &str) -> Result<Vec<String>, SerializerError> {
+    match pointer::parse(pointer) {
+        Some(segments) => Ok(segments),
+        None => Err(SerializerError::InvalidPointer(String::from(pointer))),
+    }
+}
+
+/// Serializes a value into a subtree addressed by a pointer
+///
+/// Nothing outside of the addressed subtree is touched, missing parent
+/// directories are created. An element can be appended to a sequence by
+/// addressing the index past its last element.
+///
+/// ```
+/// extern crate tempdir;
+/// use tempdir::TempDir;
+/// #[macro_use] extern crate serde_derive;
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// struct Server {
+///   port: u16,
+/// }
+///
+/// extern crate serde_fs;
+/// use serde_fs::{from_fs, to_fs, set, remove};
+///
+/// fn main() {
+///   let tmp = TempDir::new("serde_fs").unwrap();
+///   to_fs(tmp.path(), &vec![Server { port: 80 }, Server { port: 81 }]).unwrap();
+///   set(tmp.path(), "0/port", &8080).unwrap();
+///   set(tmp.path(), "2", &Server { port: 82 }).unwrap();
+///   remove(tmp.path(), "1").unwrap();
+///   let servers: Vec<Server> = from_fs(tmp.path()).unwrap();
+///   assert_eq!(servers, vec![Server { port: 8080 }, Server { port: 82 }]);
+/// }
+///
+/// ```
+pub fn set<T: ?Sized + Serialize, P: AsRef<Path>>(root: P, pointer: &str, value: &T) -> Result<(), SerializerError> {
+    set_with(root, pointer, value, &SerializerOptions::default())
+}
+
+/// Serializes a value into a subtree addressed by a pointer using given options
+///
+/// Pointer segments are mapped to file names with the options' key codec.
+pub fn set_with<T: ?Sized + Serialize, P: AsRef<Path>>(root: P, pointer: &str, value: &T, options: &SerializerOptions) -> Result<(), SerializerError> {
+    let segments = parse_pointer(pointer)?;
+    let set = |path: &Path| value.serialize(FilesystemSerializer::with_options(path, options.clone()).descend(&segments)?);
+    if options.is_atomic() {
+        atomic::replace(root, set)
+    } else {
+        set(root.as_ref())
+    }
+}
+
+/// Removes a subtree addressed by a pointer
+///
+/// Removing a sequence element shifts the elements following it (with
+/// `SerializerOptions::atomic`, the whole shift becomes visible at once). If
+/// any of the pointer's segments doesn't exist, `SerializerError::MissingSegment`
+/// reports the pointer up to that segment. The root itself can't be removed
+/// (an empty pointer results in `SerializerError::InvalidPointer`).
+pub fn remove<P: AsRef<Path>>(root: P, pointer: &str) -> Result<(), SerializerError> {
+    remove_with(root, pointer, &SerializerOptions::default())
+}
+
+/// Removes a subtree addressed by a pointer using given options
+pub fn remove_with<P: AsRef<Path>>(root: P, pointer: &str, options: &SerializerOptions) -> Result<(), SerializerError> {
+    let segments = parse_pointer(pointer)?;
+    let remove = |path: &Path| FilesystemSerializer::with_options(path, options.clone()).remove_at(&segments);
+    if options.is_atomic() {
+        atomic::replace(root, remove)
+    } else {
+        remove(root.as_ref())
+    }
+}
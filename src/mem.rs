@@ -0,0 +1,245 @@
+//! In-memory storage
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use backend::{Backend, Kind};
+
+#[derive(Debug, Clone)]
+enum Entry {
+    File(Vec<u8>),
+    Dir,
+}
+
+/// File system kept in memory
+///
+/// Behaves like the local file system (without symbolic links or
+/// permissions): files can only be written into existing directories,
+/// and removing a directory removes everything in it. The root (an empty
+/// path or `/`) always exists.
+///
+/// ```
+/// use std::sync::Arc;
+/// use serde_fs::{MemFs, Backend, to_backend, from_backend};
+///
+/// let fs = Arc::new(MemFs::new());
+/// to_backend(fs.clone(), "config", &vec![80, 443]).unwrap();
+/// assert_eq!(fs.read("config/1".as_ref()).unwrap(), b"443");
+/// let ports: Vec<u16> = from_backend(fs, "config").unwrap();
+/// assert_eq!(ports, vec![80, 443]);
+/// ```
+#[derive(Debug, Default)]
+pub struct MemFs {
+    entries: Mutex<BTreeMap<PathBuf, Entry>>,
+}
+
+impl MemFs {
+    pub fn new() -> Self {
+        MemFs::default()
+    }
+
+    /// Paths of all files and directories, in order
+    pub fn paths(&self) -> Vec<PathBuf> {
+        match self.entries.lock() {
+            Ok(entries) => entries.keys().cloned().collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, BTreeMap<PathBuf, Entry>>, io::Error> {
+        self.entries.lock().map_err(|_| io::Error::other("poisoned file system"))
+    }
+}
+
+/// Drops `.` and resolves `..` components, so that every entry has a single path
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => (),
+        }
+    }
+    normalized
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display()))
+}
+
+fn kind(entries: &BTreeMap<PathBuf, Entry>, path: &Path) -> Option<Kind> {
+    if path == Path::new("") {
+        return Some(Kind::Dir);
+    }
+    entries.get(path).map(|entry| match *entry {
+        Entry::File(_) => Kind::File,
+        Entry::Dir => Kind::Dir,
+    })
+}
+
+/// Paths of an entry and everything in it
+fn subtree(entries: &BTreeMap<PathBuf, Entry>, path: &Path) -> Vec<PathBuf> {
+    entries.range(path.to_path_buf()..)
+        .take_while(|&(entry, _)| entry.starts_with(path))
+        .map(|(entry, _)| entry.clone()).collect()
+}
+
+impl Backend for MemFs {
+    fn kind(&self, path: &Path) -> Result<Option<Kind>, io::Error> {
+        Ok(kind(&*self.lock()?, &normalize(path)))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<String>, io::Error> {
+        let entries = self.lock()?;
+        let path = normalize(path);
+        if kind(&entries, &path) != Some(Kind::Dir) {
+            return Err(not_found(&path));
+        }
+        Ok(subtree(&entries, &path).into_iter()
+           .filter(|entry| entry.parent() == Some(&path))
+           .filter_map(|entry| entry.file_name().map(|name| name.to_string_lossy().into_owned()))
+           .collect())
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, io::Error> {
+        let path = normalize(path);
+        match self.lock()?.get(&path) {
+            Some(Entry::File(content)) => Ok(content.clone()),
+            _ => Err(not_found(&path)),
+        }
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), io::Error> {
+        let mut entries = self.lock()?;
+        let path = normalize(path);
+        for dir in path.ancestors().collect::<Vec<_>>().into_iter().rev() {
+            match kind(&entries, dir) {
+                Some(Kind::Dir) => (),
+                Some(Kind::File) => return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                                              format!("{} is a file", dir.display()))),
+                None => {
+                    entries.insert(dir.to_path_buf(), Entry::Dir);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<(), io::Error> {
+        let mut entries = self.lock()?;
+        let path = normalize(path);
+        let parent = path.parent().ok_or_else(|| not_found(&path))?;
+        if kind(&entries, parent) != Some(Kind::Dir) {
+            return Err(not_found(parent));
+        }
+        if kind(&entries, &path) == Some(Kind::Dir) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} is a directory", path.display())));
+        }
+        entries.insert(path, Entry::File(content.to_vec()));
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), io::Error> {
+        let mut entries = self.lock()?;
+        let path = normalize(path);
+        if !entries.contains_key(&path) {
+            return Err(not_found(&path));
+        }
+        for entry in subtree(&entries, &path) {
+            entries.remove(&entry);
+        }
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), io::Error> {
+        let mut entries = self.lock()?;
+        let (from, to) = (normalize(from), normalize(to));
+        if !entries.contains_key(&from) {
+            return Err(not_found(&from));
+        }
+        if to.parent().map(|parent| kind(&entries, parent)) != Some(Some(Kind::Dir)) {
+            return Err(not_found(&to));
+        }
+        for entry in subtree(&entries, &to) {
+            entries.remove(&entry);
+        }
+        for entry in subtree(&entries, &from) {
+            if let Some(moved) = entries.remove(&entry) {
+                let relative = entry.strip_prefix(&from).map(Path::to_path_buf).unwrap_or_default();
+                entries.insert(to.join(relative), moved);
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, path: &Path) -> Result<Option<PathBuf>, io::Error> {
+        Ok(Some(normalize(path)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Arc;
+
+    use super::*;
+    use ser::{FilesystemSerializer, SerializerOptions, Prune};
+    use de::{FilesystemDeserializer, DeserializerOptions};
+    use serde::{Serialize, Deserialize};
+
+    #[test]
+    fn operations() {
+        let fs = MemFs::new();
+        assert_eq!(fs.kind(Path::new("")).unwrap(), Some(Kind::Dir));
+        assert!(fs.write(Path::new("a/b"), b"1").is_err());
+        fs.create_dir(Path::new("a/c")).unwrap();
+        fs.write(Path::new("a/b"), b"1").unwrap();
+        assert_eq!(fs.read(Path::new("./a/b")).unwrap(), b"1");
+        assert!(fs.create_dir(Path::new("a/b/d")).is_err());
+        let mut names = fs.read_dir(Path::new("a")).unwrap();
+        names.sort();
+        assert_eq!(names, vec!["b", "c"]);
+        fs.rename(Path::new("a"), Path::new("e")).unwrap();
+        assert_eq!(fs.paths(), vec![PathBuf::from("e"), PathBuf::from("e/b"), PathBuf::from("e/c")]);
+        fs.remove(Path::new("e")).unwrap();
+        assert!(fs.paths().is_empty());
+        assert!(fs.remove(Path::new("e")).is_err());
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Mode {
+        Plain,
+        Tls { cert: String },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Server {
+        host: String,
+        ports: Vec<u16>,
+        mode: Mode,
+        backup: Option<String>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let fs = Arc::new(MemFs::new());
+        let server = Server { host: "localhost".into(), ports: vec![80, 443], mode: Mode::Tls { cert: "cert.pem".into() },
+                              backup: Some("backup".into()) };
+        let options = SerializerOptions::new().prune(Prune::Exact(vec![]));
+        server.serialize(FilesystemSerializer::with_backend("/server", options.clone(), fs.clone())).unwrap();
+        let deserializer = FilesystemDeserializer::with_backend("/server", DeserializerOptions::new(), fs.clone());
+        assert_eq!(Server::deserialize(deserializer.clone()).unwrap(), server);
+        assert_eq!(fs.read(Path::new("server/mode/variant")).unwrap(), b"Tls");
+
+        let server = Server { host: "localhost".into(), ports: vec![80], mode: Mode::Plain, backup: None };
+        server.serialize(FilesystemSerializer::with_backend("/server", options, fs.clone())).unwrap();
+        assert_eq!(Server::deserialize(deserializer).unwrap(), server);
+        assert_eq!(fs.paths().len(), 5);
+    }
+
+}
@@ -0,0 +1,99 @@
+//! Serialization and deserialization off the async runtime's worker threads
+
+use std::io;
+use std::panic;
+use std::pin::Pin;
+use std::future::Future;
+use std::task::{Context, Poll};
+use std::path::PathBuf;
+
+use tokio::task::{self, JoinHandle};
+
+/// Serialization or deserialization running on tokio's blocking thread pool
+///
+/// Like other futures, it does nothing until polled, which has to happen
+/// within a tokio runtime.
+pub struct Blocking<T, E> {
+    task: Option<Box<dyn FnOnce() -> Result<T, E> + Send>>,
+    handle: Option<JoinHandle<Result<T, E>>>,
+}
+
+impl<T, E> Blocking<T, E> {
+    pub(crate) fn new<F>(f: F) -> Self where F: FnOnce() -> Result<T, E> + Send + 'static {
+        Blocking { task: Some(Box::new(f)), handle: None }
+    }
+}
+
+impl<T: Send + 'static, E: From<io::Error> + Send + 'static> Future for Blocking<T, E> {
+    type Output = Result<T, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if let Some(task) = self.task.take() {
+            self.handle = Some(task::spawn_blocking(task));
+        }
+        let handle = match self.handle {
+            Some(ref mut handle) => handle,
+            None => panic!("polled after completion"),
+        };
+        match Pin::new(handle).poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(result) => {
+                self.handle = None;
+                match result {
+                    Ok(result) => Poll::Ready(result),
+                    Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+                    // The runtime is shutting down
+                    Err(err) => Poll::Ready(Err(io::Error::other(err).into())),
+                }
+            },
+        }
+    }
+}
+
+/// Owned copy of a path, to be moved to the blocking thread pool
+pub(crate) fn owned<P: AsRef<::std::path::Path>>(path: P) -> PathBuf {
+    path.as_ref().to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tempdir::TempDir;
+    use tokio::runtime::Builder;
+    use ser::{Error as SerializerError, SerializerOptions};
+    use de::Error as DeserializerError;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct Server {
+        host: String,
+        ports: Vec<u16>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let server = Server { host: "localhost".into(), ports: vec![80, 443] };
+        let runtime = Builder::new_current_thread().build().unwrap();
+        runtime.block_on(::to_fs_async(tmp.path(), server.clone())).unwrap();
+        let read: Server = runtime.block_on(::from_fs_async(tmp.path())).unwrap();
+        assert_eq!(read, server);
+        assert_eq!(::from_fs::<_, Server>(tmp.path()).unwrap(), server);
+    }
+
+    #[test]
+    fn errors() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let options = SerializerOptions::new();
+        let written = runtime.block_on(::to_fs_async_with(tmp.path(), vec![vec![1u8]], &options));
+        assert!(written.is_ok());
+        let read = runtime.block_on(::from_fs_async::<_, Server>(tmp.path()));
+        assert_matches!(read, Err(DeserializerError::IoError(_)));
+        let mut map = ::std::collections::BTreeMap::new();
+        map.insert(vec![1u8], 1u8);
+        let written = runtime.block_on(::to_fs_async(tmp.path(), map));
+        assert_matches!(written, Err(SerializerError::KeyMustBeAString));
+    }
+
+}
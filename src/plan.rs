@@ -0,0 +1,436 @@
+//! Planning serialization without touching the file system
+//!
+//! The serializer runs against an overlay on top of the existing tree: reads
+//! see the tree as modified so far, while modifications are only recorded as
+//! operations.
+
+use std::io;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::Serialize;
+
+use backend::{Backend, Kind, StdFs};
+use ser::{Error, FilesystemSerializer, SerializerOptions};
+use value::{self, Value};
+use diff;
+
+/// A single planned file system operation
+///
+/// Paths are relative to the plan's root (an empty path is the root itself).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    CreateDir(PathBuf),
+    CreateFile {
+        path: PathBuf,
+        content: Vec<u8>,
+    },
+    Overwrite {
+        path: PathBuf,
+        old: Vec<u8>,
+        new: Vec<u8>,
+    },
+    RemoveFile(PathBuf),
+    RemoveDir(PathBuf),
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operation::CreateDir(ref path) => write!(f, "mkdir {}", path.display()),
+            Operation::CreateFile { ref path, ref content } =>
+                write!(f, "create {}: {:?}", path.display(), String::from_utf8_lossy(content)),
+            Operation::Overwrite { ref path, ref old, ref new } =>
+                write!(f, "overwrite {}: {:?} -> {:?}", path.display(),
+                       String::from_utf8_lossy(old), String::from_utf8_lossy(new)),
+            Operation::RemoveFile(ref path) => write!(f, "rm {}", path.display()),
+            Operation::RemoveDir(ref path) => write!(f, "rmdir {}", path.display()),
+        }
+    }
+}
+
+/// Operations serialization would perform, in order
+#[derive(Debug, Clone)]
+pub struct Plan {
+    root: PathBuf,
+    options: SerializerOptions,
+    operations: Vec<Operation>,
+}
+
+impl Plan {
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Performs the planned operations
+    ///
+    /// The tree is expected to be in the same state it was in when the plan
+    /// was made. Each operation's target is checked first (files to be
+    /// overwritten must still have their old contents, entries to be removed
+    /// must still exist and files to be created must not), and the first one
+    /// that doesn't match fails with `Error::Stale`. Operations performed
+    /// before that are not undone.
+    pub fn apply(&self) -> Result<(), Error> {
+        let backend = StdFs::new(&self.options);
+        for operation in &self.operations {
+            match *operation {
+                Operation::CreateDir(ref path) => {
+                    let path = self.root.join(path);
+                    expect(&backend, &path, |kind| kind != Some(Kind::File))?;
+                    backend.create_dir(&path)?;
+                },
+                Operation::CreateFile { ref path, ref content } => {
+                    let path = self.root.join(path);
+                    expect(&backend, &path, |kind| kind.is_none())?;
+                    backend.write(&path, content)?;
+                },
+                Operation::Overwrite { ref path, ref old, ref new } => {
+                    let path = self.root.join(path);
+                    expect(&backend, &path, |kind| kind == Some(Kind::File))?;
+                    if backend.read(&path)? != *old {
+                        return Err(Error::Stale(path));
+                    }
+                    backend.write(&path, new)?;
+                },
+                Operation::RemoveFile(ref path) => {
+                    let path = self.root.join(path);
+                    expect(&backend, &path, |kind| kind == Some(Kind::File))?;
+                    backend.remove(&path)?;
+                },
+                Operation::RemoveDir(ref path) => {
+                    let path = self.root.join(path);
+                    expect(&backend, &path, |kind| kind == Some(Kind::Dir))?;
+                    backend.remove(&path)?;
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fails with `Error::Stale` unless the kind of the entry at `path` is as expected
+fn expect<F: Fn(Option<Kind>) -> bool>(backend: &StdFs, path: &Path, expected: F) -> Result<(), Error> {
+    if expected(backend.kind(path)?) {
+        Ok(())
+    } else {
+        Err(Error::Stale(path.to_path_buf()))
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for operation in &self.operations {
+            writeln!(f, "{}", operation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serializes `value` to `path` without modifying anything, returning what
+/// would have been done instead
+pub(crate) fn plan<T: ?Sized + Serialize, P: AsRef<Path>>(path: P, value: &T, options: &SerializerOptions) -> Result<Plan, Error> {
+    let overlay = Overlay::new(path.as_ref());
+    value.serialize(FilesystemSerializer::with_backend(path.as_ref(), options.clone(), overlay.clone()))?;
+    Ok(overlay.take_plan(options.clone()))
+}
+
+/// Serializes `value` into a `Value`, as `path` would look like after serializing
+/// it there
+pub(crate) fn to_value<T: ?Sized + Serialize, P: AsRef<Path>>(path: P, value: &T, options: &SerializerOptions) -> Result<Value, diff::Error> {
+    let overlay = Overlay::new(path.as_ref());
+    value.serialize(FilesystemSerializer::with_backend(path.as_ref(), options.clone(), overlay.clone()))?;
+    Ok(value::read_from(&*overlay, path.as_ref())?)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    File(Vec<u8>),
+    /// A directory created by the plan (nothing of the tree below it is visible)
+    Dir,
+    Removed,
+}
+
+#[derive(Default)]
+struct State {
+    nodes: BTreeMap<PathBuf, Node>,
+    operations: Vec<Operation>,
+}
+
+/// Backend recording operations instead of performing them
+struct Overlay {
+    root: PathBuf,
+    base: StdFs,
+    state: Mutex<State>,
+}
+
+impl Overlay {
+    fn new<P: AsRef<Path>>(root: P) -> Arc<Self> {
+        Arc::new(Overlay {
+            root: root.as_ref().to_path_buf(),
+            base: StdFs::default(),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    fn take_plan(&self, options: SerializerOptions) -> Plan {
+        let operations = match self.state.lock() {
+            Ok(mut state) => state.operations.drain(..).collect(),
+            Err(_) => vec![],
+        };
+        Plan { root: self.root.clone(), options, operations }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, State>, io::Error> {
+        self.state.lock().map_err(|_| io::Error::other("poisoned plan state"))
+    }
+
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.root).unwrap_or(path).to_path_buf()
+    }
+
+    /// Looks `path` up in the overlay: `Some` if the overlay decides what's
+    /// at `path`, `None` if the underlying tree does
+    fn lookup(state: &State, path: &Path) -> Option<Option<Node>> {
+        if let Some(node) = state.nodes.get(path) {
+            return Some(match *node {
+                Node::Removed => None,
+                ref node => Some(node.clone()),
+            });
+        }
+        for ancestor in path.ancestors().skip(1) {
+            match state.nodes.get(ancestor) {
+                Some(&Node::Dir) | Some(&Node::Removed) | Some(&Node::File(_)) => return Some(None),
+                None => (),
+            }
+        }
+        None
+    }
+
+    fn kind_in(&self, state: &State, path: &Path) -> Result<Option<Kind>, io::Error> {
+        match Overlay::lookup(state, path) {
+            Some(Some(Node::File(_))) => Ok(Some(Kind::File)),
+            Some(Some(_)) => Ok(Some(Kind::Dir)),
+            Some(None) => Ok(None),
+            None => self.base.kind(path),
+        }
+    }
+
+    fn not_found(path: &Path) -> io::Error {
+        io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display()))
+    }
+}
+
+impl Backend for Overlay {
+    fn kind(&self, path: &Path) -> Result<Option<Kind>, io::Error> {
+        let state = self.lock()?;
+        self.kind_in(&state, path)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, io::Error> {
+        let state = self.lock()?;
+        match Overlay::lookup(&state, path) {
+            Some(node) => Ok(node.is_some()),
+            None => self.base.exists(path),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<String>, io::Error> {
+        let state = self.lock()?;
+        let mut names = match Overlay::lookup(&state, path) {
+            Some(Some(Node::Dir)) => vec![],
+            Some(_) => return Err(Overlay::not_found(path)),
+            None => self.base.read_dir(path)?,
+        };
+        for (entry, node) in state.nodes.range(path.to_path_buf()..) {
+            if !entry.starts_with(path) {
+                break;
+            }
+            if entry.parent() != Some(path) {
+                continue;
+            }
+            let name = entry.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            names.retain(|n| *n != name);
+            if *node != Node::Removed {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, io::Error> {
+        let state = self.lock()?;
+        match Overlay::lookup(&state, path) {
+            Some(Some(Node::File(content))) => Ok(content),
+            Some(_) => Err(Overlay::not_found(path)),
+            None => self.base.read(path),
+        }
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), io::Error> {
+        let mut state = self.lock()?;
+        let mut missing = vec![];
+        for ancestor in path.ancestors() {
+            if ancestor == Path::new("") || self.kind_in(&state, ancestor)?.is_some() {
+                break;
+            }
+            missing.push(ancestor.to_path_buf());
+        }
+        for dir in missing.into_iter().rev() {
+            let operation = Operation::CreateDir(self.relative(&dir));
+            state.nodes.insert(dir, Node::Dir);
+            state.operations.push(operation);
+        }
+        Ok(())
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<(), io::Error> {
+        let mut state = self.lock()?;
+        let operation = if self.kind_in(&state, path)? == Some(Kind::File) {
+            let old = match Overlay::lookup(&state, path) {
+                Some(Some(Node::File(content))) => content,
+                _ => self.base.read(path)?,
+            };
+            Operation::Overwrite { path: self.relative(path), old, new: content.to_vec() }
+        } else {
+            Operation::CreateFile { path: self.relative(path), content: content.to_vec() }
+        };
+        state.nodes.insert(path.to_path_buf(), Node::File(content.to_vec()));
+        state.operations.push(operation);
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), io::Error> {
+        let mut state = self.lock()?;
+        let operation = match self.kind_in(&state, path)? {
+            Some(Kind::Dir) => Operation::RemoveDir(self.relative(path)),
+            Some(Kind::File) => Operation::RemoveFile(self.relative(path)),
+            None => return Err(Overlay::not_found(path)),
+        };
+        let descendants: Vec<_> = state.nodes.range(path.to_path_buf()..)
+            .take_while(|&(entry, _)| entry.starts_with(path))
+            .map(|(entry, _)| entry.clone()).collect();
+        for entry in descendants {
+            state.nodes.remove(&entry);
+        }
+        state.nodes.insert(path.to_path_buf(), Node::Removed);
+        state.operations.push(operation);
+        Ok(())
+    }
+
+    fn rename(&self, _from: &Path, _to: &Path) -> Result<(), io::Error> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "renames can't be planned"))
+    }
+
+    fn resolve(&self, path: &Path) -> Result<Option<PathBuf>, io::Error> {
+        self.base.resolve(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use super::*;
+    use tempdir::TempDir;
+    use ser::Prune;
+
+    #[derive(Serialize)]
+    struct Struct {
+        test: u8,
+        passed: Option<u64>,
+        list: Vec<u8>,
+    }
+
+    #[test]
+    fn create() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        let plan = plan(&path, &Struct { test: 1, passed: Some(2), list: vec![3] }, &SerializerOptions::new()).unwrap();
+        assert!(!path.exists());
+        assert_eq!(plan.operations(), &[
+            Operation::CreateDir(PathBuf::new()),
+            Operation::CreateFile { path: PathBuf::from("test"), content: b"1".to_vec() },
+            Operation::CreateFile { path: PathBuf::from("passed"), content: b"2".to_vec() },
+            Operation::CreateDir(PathBuf::from("list")),
+            Operation::CreateFile { path: PathBuf::from("list/0"), content: b"3".to_vec() },
+        ]);
+        plan.apply().unwrap();
+        assert_eq!(fs::read(path.join("list").join("0")).unwrap(), b"3");
+    }
+
+    #[test]
+    fn update() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        let options = SerializerOptions::new().skip_unchanged(true).prune(Prune::Exact(vec![]));
+        Struct { test: 1, passed: Some(2), list: vec![3, 4] }.serialize(FilesystemSerializer::new(&path)).unwrap();
+        fs::write(path.join("extra"), "").unwrap();
+        let plan = plan(&path, &Struct { test: 5, passed: None, list: vec![3] }, &options).unwrap();
+        assert_eq!(fs::read(path.join("test")).unwrap(), b"1");
+        assert_eq!(plan.operations(), &[
+            Operation::Overwrite { path: PathBuf::from("test"), old: b"1".to_vec(), new: b"5".to_vec() },
+            Operation::RemoveFile(PathBuf::from("passed")),
+            Operation::RemoveFile(PathBuf::from("list/1")),
+            Operation::RemoveFile(PathBuf::from("extra")),
+        ]);
+        plan.apply().unwrap();
+        assert_eq!(fs::read(path.join("test")).unwrap(), b"5");
+        assert!(!path.join("passed").exists());
+        assert!(!path.join("extra").exists());
+        assert_eq!(fs::read_dir(path.join("list")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn stale() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        Struct { test: 1, passed: Some(2), list: vec![3] }.serialize(FilesystemSerializer::new(&path)).unwrap();
+        let plan = plan(&path, &Struct { test: 5, passed: None, list: vec![3] }, &SerializerOptions::new()).unwrap();
+        fs::write(path.join("test"), "7").unwrap();
+        assert_matches!(plan.apply(), Err(Error::Stale(ref stale)) if *stale == path.join("test"));
+        assert_eq!(fs::read(path.join("test")).unwrap(), b"7");
+        fs::write(path.join("test"), "1").unwrap();
+        fs::remove_file(path.join("passed")).unwrap();
+        assert_matches!(plan.apply(), Err(Error::Stale(ref stale)) if *stale == path.join("passed"));
+        let plan = super::plan(&path, &Struct { test: 5, passed: Some(2), list: vec![3] }, &SerializerOptions::new()).unwrap();
+        fs::write(path.join("passed"), "2").unwrap();
+        assert_matches!(plan.apply(), Err(Error::Stale(ref stale)) if *stale == path.join("passed"));
+    }
+
+    #[test]
+    fn replace_dir() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        Struct { test: 1, passed: Some(2), list: vec![3, 4] }.serialize(FilesystemSerializer::new(&path)).unwrap();
+        let plan = plan(&path, &vec![1], &SerializerOptions::new().prune(Prune::Exact(vec![]))).unwrap();
+        let mut lines: Vec<_> = plan.to_string().lines().map(String::from).collect();
+        assert_eq!(lines.remove(0), "create 0: \"1\"");
+        lines.sort();
+        assert_eq!(lines, vec!["rm passed", "rm test", "rmdir list"]);
+    }
+
+    #[test]
+    fn value() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("struct");
+        Struct { test: 1, passed: Some(2), list: vec![3, 4] }.serialize(FilesystemSerializer::new(&path)).unwrap();
+        let value = to_value(&path, &Struct { test: 5, passed: None, list: vec![3] }, &SerializerOptions::new()).unwrap();
+        let mut map = BTreeMap::new();
+        map.insert(String::from("test"), Value::from("5"));
+        map.insert(String::from("list"), Value::Seq(vec![Value::from("3")]));
+        assert_eq!(value, Value::Dir(map));
+        assert_eq!(fs::read(path.join("test")).unwrap(), b"1");
+    }
+
+}
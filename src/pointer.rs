@@ -0,0 +1,73 @@
+//! Pointers addressing subtrees
+//!
+//! A pointer is a `/`-separated list of segments (map keys, struct fields or
+//! sequence indices), like `servers/0/port`. Similarly to JSON Pointer,
+//! `~1` stands for `/` and `~0` for `~` within a segment. A leading `/` is
+//! optional and the empty pointer addresses the root itself.
+
+/// Splits a pointer into unescaped segments
+///
+/// Returns `None` if the pointer has empty segments or invalid escapes.
+pub fn parse(pointer: &str) -> Option<Vec<String>> {
+    let pointer = pointer.strip_prefix('/').unwrap_or(pointer);
+    if pointer.is_empty() {
+        return Some(vec![]);
+    }
+    pointer.split('/').map(unescape).collect()
+}
+
+fn unescape(segment: &str) -> Option<String> {
+    if segment.is_empty() {
+        return None;
+    }
+    let mut unescaped = String::with_capacity(segment.len());
+    let mut chars = segment.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '~' => match chars.next() {
+                Some('0') => unescaped.push('~'),
+                Some('1') => unescaped.push('/'),
+                _ => return None,
+            },
+            c => unescaped.push(c),
+        }
+    }
+    Some(unescaped)
+}
+
+/// Escapes a key so that it can be used as a pointer segment
+pub fn escape(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+/// Joins segments back into a pointer
+pub fn join<S: AsRef<str>>(segments: &[S]) -> String {
+    segments.iter().map(|segment| escape(segment.as_ref())).collect::<Vec<_>>().join("/")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        assert_eq!(parse("").unwrap(), Vec::<String>::new());
+        assert_eq!(parse("/").unwrap(), Vec::<String>::new());
+        assert_eq!(parse("servers/0/port").unwrap(), vec!["servers", "0", "port"]);
+        assert_eq!(parse("/servers/0").unwrap(), vec!["servers", "0"]);
+        assert_eq!(parse("a~1b/~0c~01").unwrap(), vec!["a/b", "~c~1"]);
+        assert!(parse("a//b").is_none());
+        assert!(parse("a/").is_none());
+        assert!(parse("a~2").is_none());
+        assert!(parse("a~").is_none());
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("a/b~c"), "a~1b~0c");
+        assert_eq!(join(&["a/b", "0", "~"]), "a~1b/0/~0");
+        assert_eq!(parse(&join(&["a/b", "0", "~1"])).unwrap(), vec!["a/b", "0", "~1"]);
+    }
+
+}
@@ -1,5 +1,6 @@
-use std::io::{self, Write};
-use std::fs;
+use std::io;
+use std::collections::{HashSet, BTreeSet};
+use std::sync::{Arc, Mutex};
 use std::path::{Path, PathBuf};
 
 use std::fmt::Display;
@@ -10,11 +11,30 @@ use serde::ser::{SerializeSeq, SerializeTuple, SerializeTupleStruct,
                  SerializeStructVariant, Impossible};
 use serde::ser::Error as SerdeError;
 
+use key::{KeyCodec, Verbatim};
+use confine::{Root, is_local_name, is_field_name};
+use de::is_seq_names;
+use backend::{Backend, Kind, StdFs};
+use pointer;
+
 #[derive(Debug, Error)]
 pub enum Error {
     IoError(io::Error),
     KeyMustBeAString,
     #[error(non_std, no_from)]
+    PathTraversal(PathBuf),
+    #[error(non_std, no_from)]
+    InvalidPointer(String),
+    /// Pointer up to (and including) the first segment that doesn't exist
+    #[error(non_std, no_from)]
+    MissingSegment(String),
+    /// Pointer to a sequence element that is neither an existing nor the next index
+    #[error(non_std, no_from)]
+    InvalidIndex(String),
+    /// Path that is no longer in the state a plan expects it to be in
+    #[error(non_std, no_from)]
+    Stale(PathBuf),
+    #[error(non_std, no_from)]
     Custom(String),
 }
 
@@ -24,68 +44,459 @@ impl SerdeError for Error {
     }
 }
 
+/// Durability policy for leaf files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Truncate and write files in place
+    #[default]
+    None,
+    /// Write into a temporary file next to the target and rename it over the target,
+    /// so the file always holds either the old or the new content (the new file
+    /// keeps the old one's permissions, unless `SerializerOptions::file_mode` is set)
+    Rename,
+    /// Same as `Rename`, but also fsync the file before the rename and its parent
+    /// directory after it
+    Sync,
+}
+
+/// Pruning policy for directories being reserialized
+///
+/// Elements past the end of a sequence are removed regardless of the policy
+/// (once the sequence has been written, so that elements that are still
+/// there aren't recreated).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Prune {
+    /// Keep entries that were not written during serialization
+    #[default]
+    None,
+    /// Remove entries that were not written during serialization, except for
+    /// auxiliary files at the listed paths (relative to the serializer's path,
+    /// like `README.md` or `servers/README.md`)
+    Exact(Vec<String>),
+}
+
+impl Prune {
+    fn keeps(&self, path: &Path) -> bool {
+        match *self {
+            Prune::None => true,
+            Prune::Exact(ref keep) => keep.iter().any(|k| Path::new(k) == path),
+        }
+    }
+}
+
+/// Serializer configuration
+///
+/// ```
+/// use serde_fs::{SerializerOptions, Durability, Prune};
+///
+/// let options = SerializerOptions::new()
+///     .durability(Durability::Sync)
+///     .prune(Prune::Exact(vec!["README.md".into()]))
+///     .trailing_newline(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SerializerOptions {
+    pub(crate) durability: Durability,
+    prune: Prune,
+    atomic: bool,
+    trailing_newline: bool,
+    pub(crate) file_mode: Option<u32>,
+    pub(crate) dir_mode: Option<u32>,
+    key_codec: Arc<dyn KeyCodec>,
+    skip_unchanged: bool,
+}
+
+impl Default for SerializerOptions {
+    fn default() -> Self {
+        SerializerOptions {
+            durability: Durability::default(),
+            prune: Prune::default(),
+            atomic: false,
+            trailing_newline: false,
+            file_mode: None,
+            dir_mode: None,
+            key_codec: Arc::new(Verbatim),
+            skip_unchanged: false,
+        }
+    }
+}
+
+impl SerializerOptions {
+    pub fn new() -> Self {
+        SerializerOptions::default()
+    }
+
+    /// Durability policy for leaf files (`Durability::None` by default)
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Pruning policy for directories (`Prune::None` by default)
+    pub fn prune(mut self, prune: Prune) -> Self {
+        self.prune = prune;
+        self
+    }
+
+    /// Serialize the whole tree into a staging directory and swap it in (see `to_fs_atomic`)
+    ///
+    /// Only honored by `to_fs_with`, `set_with` and `remove_with`.
+    pub fn atomic(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+        self
+    }
+
+    /// Terminate textual leaf files (numbers, strings, booleans, variant names)
+    /// with a newline
+    pub fn trailing_newline(mut self, trailing_newline: bool) -> Self {
+        self.trailing_newline = trailing_newline;
+        self
+    }
+
+    /// Permissions for written files (Unix only)
+    pub fn file_mode(mut self, mode: u32) -> Self {
+        self.file_mode = Some(mode);
+        self
+    }
+
+    /// Permissions for created directories (Unix only)
+    pub fn dir_mode(mut self, mode: u32) -> Self {
+        self.dir_mode = Some(mode);
+        self
+    }
+
+    /// Mapping of map and struct keys to file names (`Verbatim` by default)
+    pub fn key_codec<K: KeyCodec + 'static>(mut self, key_codec: K) -> Self {
+        self.key_codec = Arc::new(key_codec);
+        self
+    }
+
+    /// Leave files that already have the right contents alone, so that
+    /// their modification times don't change
+    ///
+    /// With `atomic`, such files are still copied into the new tree (keeping
+    /// their modification times), so they don't keep their inodes.
+    pub fn skip_unchanged(mut self, skip_unchanged: bool) -> Self {
+        self.skip_unchanged = skip_unchanged;
+        self
+    }
+
+    pub(crate) fn is_atomic(&self) -> bool {
+        self.atomic
+    }
+}
+
+/// State shared by all serializers of a tree
+struct Context {
+    root: Root,
+    base: PathBuf,
+    options: SerializerOptions,
+    backend: Arc<dyn Backend>,
+    changes: Mutex<BTreeSet<PathBuf>>,
+}
+
+impl Context {
+    /// Records a written, created or removed path
+    fn changed<P: AsRef<Path>>(&self, path: P) {
+        // Parents of the root created for a leaf value are not reported
+        if let Ok(relative) = path.as_ref().strip_prefix(&self.base) {
+            if let Ok(mut changes) = self.changes.lock() {
+                changes.insert(relative.to_path_buf());
+            }
+        }
+    }
+
+    /// Fails unless `path` stays within the root directory
+    fn confine<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        if self.root.contains(path.as_ref(), &*self.backend)? {
+            Ok(())
+        } else {
+            Err(Error::PathTraversal(path.as_ref().to_path_buf()))
+        }
+    }
+
+    fn is_seq(&self, path: &Path) -> Result<bool, io::Error> {
+        Ok(self.backend.kind(path)? == Some(Kind::Dir) && is_seq_names(&self.backend.read_dir(path)?))
+    }
+}
+
 #[derive(Clone)]
 pub struct FilesystemSerializer<P: AsRef<Path>> {
     path: P,
+    context: Arc<Context>,
 }
 
 impl<P: AsRef<Path>> FilesystemSerializer<P> {
     pub fn new(path: P) -> Self {
-        FilesystemSerializer { path }
+        FilesystemSerializer::with_options(path, SerializerOptions::default())
+    }
+
+    /// Creates a serializer with given options
+    ///
+    /// The serializer will never access anything outside of `path`.
+    pub fn with_options(path: P, options: SerializerOptions) -> Self {
+        let backend = Arc::new(StdFs::new(&options));
+        FilesystemSerializer::with_backend(path, options, backend)
+    }
+
+    /// Creates a serializer writing to a given backend
+    ///
+    /// Durability and permissions options are up to the backend (see `StdFs::new`).
+    pub fn with_backend(path: P, options: SerializerOptions, backend: Arc<dyn Backend>) -> Self {
+        let root = Root::new(path.as_ref(), &*backend);
+        let base = path.as_ref().to_path_buf();
+        let context = Context { root, base, options, backend, changes: Mutex::new(BTreeSet::new()) };
+        FilesystemSerializer { path, context: Arc::new(context) }
+    }
+
+    /// Paths (relative to the serializer's path) that were written, created
+    /// or removed so far, including by clones of this serializer
+    ///
+    /// An empty path stands for the serializer's path itself.
+    pub fn changes(&self) -> Vec<PathBuf> {
+        match self.context.changes.lock() {
+            Ok(changes) => changes.iter().cloned().collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    fn child<Q: AsRef<Path>>(&self, path: Q) -> FilesystemSerializer<Q> {
+        FilesystemSerializer { path, context: self.context.clone() }
+    }
+
+    fn write_text<V: AsRef<str>>(self, text: V) -> Result<(), Error> {
+        if self.context.options.trailing_newline {
+            write_to_file(self.path, format!("{}\n", text.as_ref()), &self.context)
+        } else {
+            write_to_file(self.path, text.as_ref(), &self.context)
+        }
+    }
+
+    fn write_variant(&self, variant: &str) -> Result<(), Error> {
+        self.child(self.path.as_ref().join("variant")).write_text(variant)
+    }
+
+    /// Removes the value (`value`, or elements of a tuple variant) of a
+    /// different variant the directory holds
+    ///
+    /// Fields of a struct variant can't be told apart from auxiliary files,
+    /// so they are left to `Prune`.
+    fn clear_other_variant(&self, variant: &str) -> Result<(), Error> {
+        let path = self.path.as_ref();
+        if self.context.backend.kind(&path.join("variant"))? != Some(Kind::File) {
+            return Ok(());
+        }
+        let current = self.context.backend.read(&path.join("variant"))?;
+        let expected = if self.context.options.trailing_newline { format!("{}\n", variant) } else { String::from(variant) };
+        if current == expected.as_bytes() {
+            return Ok(());
+        }
+        for name in self.context.backend.read_dir(path)? {
+            if name == "value" || name.parse::<usize>().ok().filter(|index| name == index.to_string()).is_some() {
+                remove(path.join(name), &self.context)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Descends into a subtree addressed by pointer segments
+    ///
+    /// The returned serializer is still confined to the original root.
+    /// Elements of existing sequences can only be addressed by an existing
+    /// index or the next one (to append an element). Empty directories may be
+    /// empty sequences, so an index can only address their first element.
+    pub(crate) fn descend<S: AsRef<str>>(&self, segments: &[S]) -> Result<FilesystemSerializer<PathBuf>, Error> {
+        let mut path = self.path.as_ref().to_path_buf();
+        for (i, segment) in segments.iter().enumerate() {
+            let name = self.context.options.key_codec.encode(segment.as_ref());
+            if !is_local_name(&name) {
+                return Err(Error::PathTraversal(path.join(name)));
+            }
+            if self.context.backend.kind(&path)? == Some(Kind::Dir) {
+                let names = self.context.backend.read_dir(&path)?;
+                let index = name.parse::<usize>().ok().filter(|index| name == index.to_string());
+                if is_seq_names(&names) || (names.is_empty() && index.is_some()) {
+                    match index {
+                        Some(index) if index <= names.len() => (),
+                        _ => return Err(Error::InvalidIndex(pointer::join(&segments[..=i]))),
+                    }
+                }
+            }
+            path.push(name);
+            self.context.confine(&path)?;
+        }
+        Ok(self.child(path))
+    }
+
+    /// Removes a subtree addressed by pointer segments
+    ///
+    /// Removing a sequence element shifts the elements following it. The root
+    /// itself (addressed by no segments) can't be removed.
+    pub(crate) fn remove_at<S: AsRef<str>>(&self, segments: &[S]) -> Result<(), Error> {
+        if segments.is_empty() {
+            return Err(Error::InvalidPointer(String::new()));
+        }
+        let target = self.descend(segments)?;
+        let path = target.path.as_path();
+        let backend = &self.context.backend;
+        if !backend.exists(path)? {
+            let mut prefix = self.path.as_ref().to_path_buf();
+            for (i, segment) in segments.iter().enumerate() {
+                prefix.push(self.context.options.key_codec.encode(segment.as_ref()));
+                if !backend.exists(&prefix)? {
+                    return Err(Error::MissingSegment(pointer::join(&segments[..=i])));
+                }
+            }
+            return Err(Error::MissingSegment(String::new()));
+        }
+        let parent = match path.parent() {
+            Some(parent) if self.context.is_seq(parent)? => parent,
+            _ => {
+                remove(path, &self.context)?;
+                return Ok(());
+            },
+        };
+        let len = backend.read_dir(parent)?.len();
+        let index: usize = path.file_name().and_then(|name| name.to_str())
+            .and_then(|name| name.parse().ok()).unwrap_or(len);
+        // The element is moved aside until the following ones are shifted,
+        // so that a failed shift can be rolled back
+        let removed = parent.join(format!(".{}.serde_fs-removed", index));
+        backend.rename(path, &removed)?;
+        for i in index + 1..len {
+            let (from, to) = (parent.join(i.to_string()), parent.join((i - 1).to_string()));
+            if let Err(err) = backend.rename(&from, &to) {
+                for j in (index + 1..i).rev() {
+                    backend.rename(&parent.join((j - 1).to_string()), &parent.join(j.to_string()))?;
+                }
+                backend.rename(&removed, path)?;
+                return Err(err.into());
+            }
+            self.context.changed(from);
+            self.context.changed(to);
+        }
+        backend.remove(&removed)?;
+        self.context.changed(path);
+        Ok(())
+    }
+}
+
+/// Removes entries of a directory that were neither written nor are to be kept
+fn prune_dir<P: AsRef<Path>>(path: P, written: &HashSet<String>, context: &Context) -> Result<(), io::Error> {
+    let prune = &context.options.prune;
+    if *prune == Prune::None {
+        return Ok(());
+    }
+    for name in context.backend.read_dir(path.as_ref())? {
+        let entry = path.as_ref().join(&name);
+        let relative = entry.strip_prefix(&context.base).unwrap_or(&entry).to_path_buf();
+        if !written.contains(&name) && !prune.keeps(&relative) {
+            remove(entry, context)?;
+        }
+    }
+    Ok(())
+}
+
+fn create_dir<P: AsRef<Path>>(path: P, context: &Context) -> Result<(), Error> {
+    context.confine(path.as_ref())?;
+    make_dir(path, context)?;
+    Ok(())
+}
+
+fn make_dir<P: AsRef<Path>>(path: P, context: &Context) -> Result<(), io::Error> {
+    if context.backend.kind(path.as_ref())? != Some(Kind::Dir) {
+        context.backend.create_dir(path.as_ref())?;
+        context.changed(path.as_ref());
+        Ok(())
+    } else if path.as_ref().starts_with(&context.base) {
+        context.backend.set_permissions(path.as_ref())
+    } else {
+        // Parents of the root are not ours to change
+        Ok(())
     }
 }
 
+fn remove<P: AsRef<Path>>(path: P, context: &Context) -> Result<(), io::Error> {
+    context.backend.remove(path.as_ref())?;
+    context.changed(path);
+    Ok(())
+}
+
 pub struct FilesystemSequenceSerializer<P: AsRef<Path>> {
-    path: P,
+    serializer: FilesystemSerializer<P>,
     counter: usize,
+    written: HashSet<String>,
 }
 
 impl<P: AsRef<Path>> FilesystemSequenceSerializer<P> {
-    pub fn new(path: P) -> Result<Self, Error> {
-        if path.as_ref().is_dir() {
-            let files = fs::read_dir(path.as_ref())?;
-            for file in files.filter(Result::is_ok).map(Result::unwrap) {
-                if let Ok(_) = file.file_name().to_str().unwrap().parse::<usize>() {
-                    fs::remove_file(file.path())?;
-                }
-            }
-        } else if path.as_ref().is_file() {
-            fs::remove_file(path.as_ref())?;
+    pub fn new(serializer: FilesystemSerializer<P>) -> Result<Self, Error> {
+        serializer.context.confine(serializer.path.as_ref())?;
+        if serializer.context.backend.kind(serializer.path.as_ref())? == Some(Kind::File) {
+            remove(serializer.path.as_ref(), &serializer.context)?;
         }
-        fs::create_dir_all(path.as_ref())?;
+        create_dir(serializer.path.as_ref(), &serializer.context)?;
         Ok(FilesystemSequenceSerializer {
-            path, counter: 0
+            serializer, counter: 0, written: HashSet::new(),
         })
     }
 }
 
 pub struct FilesystemMapSerializer<P: AsRef<Path>> {
-    path: P,
+    serializer: FilesystemSerializer<P>,
     key: Option<String>,
+    written: HashSet<String>,
 }
 
 impl<P: AsRef<Path>> FilesystemMapSerializer<P> {
-    pub fn new(path: P) -> Result<Self, Error> {
-        if path.as_ref().is_file() {
-            fs::remove_file(path.as_ref())?;
+    pub fn new(serializer: FilesystemSerializer<P>) -> Result<Self, Error> {
+        serializer.context.confine(serializer.path.as_ref())?;
+        if serializer.context.backend.kind(serializer.path.as_ref())? == Some(Kind::File) {
+            remove(serializer.path.as_ref(), &serializer.context)?;
         }
-        fs::create_dir_all(path.as_ref())?;
+        create_dir(serializer.path.as_ref(), &serializer.context)?;
         Ok(FilesystemMapSerializer {
-            path, key: None
+            serializer, key: None, written: HashSet::new(),
         })
     }
+
+    fn serialize_at<T: ?Sized + Serialize>(&mut self, key: &str, value: &T, field: bool) -> Result<(), Error> {
+        let name = self.serializer.context.options.key_codec.encode(key);
+        if (field && !is_field_name(&name)) || (!field && !is_local_name(&name)) {
+            return Err(Error::PathTraversal(self.serializer.path.as_ref().join(name)));
+        }
+        let result = value.serialize(self.serializer.child(self.serializer.path.as_ref().join(&name)));
+        self.written.insert(name);
+        result
+    }
+
+    fn do_end(self) -> Result<(), Error> {
+        prune_dir(self.serializer.path.as_ref(), &self.written, &self.serializer.context)?;
+        Ok(())
+    }
 }
 
 impl<P: AsRef<Path>> FilesystemSequenceSerializer<P> {
 
      fn do_serialize_element<T: ? Sized>(&mut self, value: &T) -> Result<(), Error> where
         T: Serialize {
-        let result = value.serialize(FilesystemSerializer { path: self.path.as_ref().join(format!("{}", self.counter)) });
+        let name = format!("{}", self.counter);
+        let result = value.serialize(self.serializer.child(self.serializer.path.as_ref().join(&name)));
+        self.written.insert(name);
         self.counter += 1;
         result
     }
 
     fn do_end(self) -> Result<(), Error> {
+        // Elements past the end of the sequence are always removed
+        let path = self.serializer.path.as_ref();
+        for name in self.serializer.context.backend.read_dir(path)? {
+            if let Ok(index) = name.parse::<usize>() {
+                if index >= self.counter {
+                    remove(path.join(name), &self.serializer.context)?;
+                }
+            }
+        }
+        prune_dir(path, &self.written, &self.serializer.context)?;
         Ok(())
     }
 }
@@ -150,6 +561,10 @@ impl<P: AsRef<Path>> SerializeTupleVariant for FilesystemSequenceSerializer<P> {
     }
 }
 
+/// Serializes map keys to their textual form
+///
+/// Strings, numbers, booleans, characters and unit variants (as well as
+/// newtype structs wrapping them) are supported.
 struct MapKeySerializer;
 
 impl Serializer for MapKeySerializer {
@@ -163,52 +578,52 @@ impl Serializer for MapKeySerializer {
     type SerializeStruct = Impossible<Self::Ok, Self::Error>;
     type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
 
-    fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
-    fn serialize_i8(self, _v: i8) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
-    fn serialize_i16(self, _v: i16) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
-    fn serialize_i32(self, _v: i32) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
-    fn serialize_i64(self, _v: i64) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
-    fn serialize_u8(self, _v: u8) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
-    fn serialize_u16(self, _v: u16) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
-    fn serialize_u32(self, _v: u32) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
-    fn serialize_u64(self, _v: u64) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
-    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
-    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
-    fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(format!("{}", v))
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
@@ -236,13 +651,13 @@ impl Serializer for MapKeySerializer {
         Err(Error::KeyMustBeAString)
     }
 
-    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str) -> Result<Self::Ok, Self::Error> {
-        Err(Error::KeyMustBeAString)
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(String::from(variant))
     }
 
-    fn serialize_newtype_struct<T: ? Sized>(self, _name: &'static str, _value: &T) -> Result<Self::Ok, Self::Error> where
+    fn serialize_newtype_struct<T: ? Sized>(self, _name: &'static str, value: &T) -> Result<Self::Ok, Self::Error> where
         T: Serialize {
-        Err(Error::KeyMustBeAString)
+        value.serialize(self)
     }
 
     fn serialize_newtype_variant<T: ? Sized>(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _value: &T) -> Result<Self::Ok, Self::Error> where
@@ -286,10 +701,7 @@ impl<P: AsRef<Path>> SerializeMap for FilesystemMapSerializer<P> {
 
     fn serialize_key<T: ? Sized>(&mut self, key: &T) -> Result<(), Self::Error> where
         T: Serialize {
-        let key = key.serialize(MapKeySerializer)?;
-        fs::create_dir_all(&self.path)?;
-        fs::File::create(&key)?;
-        self.key = Some(key);
+        self.key = Some(key.serialize(MapKeySerializer)?);
         Ok(())
     }
 
@@ -299,7 +711,7 @@ impl<P: AsRef<Path>> SerializeMap for FilesystemMapSerializer<P> {
             None => return Err(Error::KeyMustBeAString),
             Some(key) => key,
         };
-        value.serialize(FilesystemSerializer { path: self.path.as_ref().join(key) })
+        self.serialize_at(&key, value, false)
     }
 
     fn serialize_entry<K: ?Sized, V: ?Sized>(
@@ -311,12 +723,12 @@ impl<P: AsRef<Path>> SerializeMap for FilesystemMapSerializer<P> {
         K: Serialize,
         V: Serialize {
         let key = key.serialize(MapKeySerializer)?;
-        value.serialize(FilesystemSerializer { path: self.path.as_ref().join(key) })
+        self.serialize_at(&key, value, false)
     }
 
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(())
+        self.do_end()
     }
 
 }
@@ -327,11 +739,11 @@ impl<P: AsRef<Path>> SerializeStruct for FilesystemMapSerializer<P> {
 
     fn serialize_field<T: ? Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error> where
         T: Serialize {
-        value.serialize(FilesystemSerializer { path: self.path.as_ref().join(key) })
+        self.serialize_at(key, value, true)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(())
+        self.do_end()
     }
 }
 
@@ -341,28 +753,44 @@ impl<P: AsRef<Path>> SerializeStructVariant for FilesystemMapSerializer<P> {
 
     fn serialize_field<T: ? Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error> where
         T: Serialize {
-        value.serialize(FilesystemSerializer { path: self.path.as_ref().join(key) })
+        self.serialize_at(key, value, true)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(())
+        self.do_end()
     }
 }
 
 #[inline]
-fn write_to_file<P: AsRef<Path>, V: AsRef<[u8]>>(path: P, content: V) -> Result<(), io::Error> {
-    if path.as_ref().is_dir() {
-        fs::remove_dir_all(path.as_ref())?;
+fn write_to_file<P: AsRef<Path>, V: AsRef<[u8]>>(path: P, content: V, context: &Context) -> Result<(), Error> {
+    let path = path.as_ref();
+    context.confine(path)?;
+    if context.backend.kind(path)? == Some(Kind::Dir) {
+        remove(path, context)?;
     }
-    let mut dir = PathBuf::from(path.as_ref());
+    let mut dir = PathBuf::from(path);
     dir.pop();
-    fs::create_dir_all(&dir)?;
+    // The parent is outside of the root if the root itself is a file
+    make_dir(&dir, context)?;
 
-    let mut file = fs::File::create(path.as_ref())?;
-    file.write(content.as_ref())?;
+    if context.options.skip_unchanged && is_unchanged(path, content.as_ref(), context)? {
+        context.backend.set_permissions(path)?;
+        return Ok(());
+    }
+    context.changed(path);
+    context.backend.write(path, content.as_ref())?;
     Ok(())
 }
 
+/// Checks whether the file at `path` has exactly the given contents
+fn is_unchanged(path: &Path, content: &[u8], context: &Context) -> Result<bool, io::Error> {
+    if context.backend.kind(path)? == Some(Kind::File) && context.backend.size(path)? == content.len() as u64 {
+        Ok(context.backend.read(path)? == content)
+    } else {
+        Ok(false)
+    }
+}
+
 impl<P: AsRef<Path>> Serializer for FilesystemSerializer<P> {
     type Ok = ();
     type Error = Error;
@@ -376,83 +804,68 @@ impl<P: AsRef<Path>> Serializer for FilesystemSerializer<P> {
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
         if v {
-            write_to_file(self.path, "true")?;
+            self.write_text("true")
         } else {
-            write_to_file(self.path, "false")?;
+            self.write_text("false")
         }
-        Ok(())
     }
 
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, format!("{}", v))?;
-        Ok(())
+        self.write_text(format!("{}", v))
     }
 
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, format!("{}", v))?;
-        Ok(())
+        self.write_text(format!("{}", v))
     }
 
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, format!("{}", v))?;
-        Ok(())
+        self.write_text(format!("{}", v))
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, format!("{}", v))?;
-        Ok(())
+        self.write_text(format!("{}", v))
     }
 
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, format!("{}", v))?;
-        Ok(())
+        self.write_text(format!("{}", v))
     }
 
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, format!("{}", v))?;
-        Ok(())
+        self.write_text(format!("{}", v))
     }
 
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, format!("{}", v))?;
-        Ok(())
+        self.write_text(format!("{}", v))
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, format!("{}", v))?;
-        Ok(())
+        self.write_text(format!("{}", v))
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, format!("{}", v))?;
-        Ok(())
+        self.write_text(format!("{}", v))
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, format!("{}", v))?;
-        Ok(())
+        self.write_text(format!("{}", v))
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, format!("{}", v))?;
-        Ok(())
+        self.write_text(format!("{}", v))
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, v)?;
-        Ok(())
+        self.write_text(v)
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, v)?;
-        Ok(())
+        write_to_file(self.path, v, &self.context)
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        if self.path.as_ref().is_file() {
-            fs::remove_file(self.path)?;
-        } else if self.path.as_ref().is_dir() {
-            fs::remove_dir_all(self.path)?;
+        self.context.confine(self.path.as_ref())?;
+        if self.context.backend.exists(self.path.as_ref())? {
+            remove(self.path.as_ref(), &self.context)?;
         }
         Ok(())
     }
@@ -463,8 +876,7 @@ impl<P: AsRef<Path>> Serializer for FilesystemSerializer<P> {
     }
 
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        write_to_file(self.path, &[])?;
-        Ok(())
+        write_to_file(self.path, [], &self.context)
     }
 
     fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
@@ -482,52 +894,53 @@ impl<P: AsRef<Path>> Serializer for FilesystemSerializer<P> {
 
     fn serialize_newtype_variant<T: ? Sized>(self, _name: &'static str, _variant_index: u32, variant: &'static str, value: &T) -> Result<Self::Ok, Self::Error> where
         T: Serialize {
-        if self.path.as_ref().is_dir() {
-            if self.path.as_ref().join("variant").is_file() {
-                fs::remove_file(self.path.as_ref().join("variant"))?;
-            }
-            if self.path.as_ref().join("value").is_file() {
-                fs::remove_file(self.path.as_ref().join("value"))?;
-            } else if self.path.as_ref().join("value").is_dir() {
-                fs::remove_dir_all(self.path.as_ref().join("value"))?;
-            }
-        } else if self.path.as_ref().is_file() {
-            fs::remove_file(self.path.as_ref())?;
+        self.context.confine(self.path.as_ref())?;
+        match self.context.backend.kind(self.path.as_ref())? {
+            Some(Kind::Dir) => self.clear_other_variant(variant)?,
+            Some(Kind::File) => remove(self.path.as_ref(), &self.context)?,
+            None => (),
         }
-        fs::create_dir_all(self.path.as_ref())?;
-        write_to_file(self.path.as_ref().join("variant"), variant)?;
-        value.serialize(FilesystemSerializer::new(self.path.as_ref().join("value")))
+        create_dir(self.path.as_ref(), &self.context)?;
+        self.write_variant(variant)?;
+        value.serialize(self.child(self.path.as_ref().join("value")))?;
+        let written = ["variant", "value"].iter().map(|name| String::from(*name)).collect();
+        prune_dir(self.path.as_ref(), &written, &self.context)?;
+        Ok(())
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        FilesystemSequenceSerializer::new(self.path)
+        FilesystemSequenceSerializer::new(self)
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        FilesystemSequenceSerializer::new(self.path)
+        FilesystemSequenceSerializer::new(self)
     }
 
     fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, Self::Error> {
-        FilesystemSequenceSerializer::new(self.path)
+        FilesystemSequenceSerializer::new(self)
     }
 
     fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        let serializer = FilesystemSequenceSerializer::new(PathBuf::from(self.path.as_ref()))?;
-        write_to_file(self.path.as_ref().join("variant"), variant)?;
+        let mut serializer = FilesystemSequenceSerializer::new(self.child(PathBuf::from(self.path.as_ref())))?;
+        self.clear_other_variant(variant)?;
+        self.write_variant(variant)?;
+        serializer.written.insert(String::from("variant"));
         Ok(serializer)
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        FilesystemMapSerializer::new(self.path)
+        FilesystemMapSerializer::new(self)
     }
 
     fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Self::Error> {
-        FilesystemMapSerializer::new(self.path)
+        FilesystemMapSerializer::new(self)
     }
 
     fn serialize_struct_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, Self::Error> {
-        let serializer = FilesystemMapSerializer::new(PathBuf::from(self.path.as_ref()))?;
-        write_to_file(self.path.as_ref().join("variant"), variant)?;
+        let mut serializer = FilesystemMapSerializer::new(self.child(PathBuf::from(self.path.as_ref())))?;
+        self.clear_other_variant(variant)?;
+        self.write_variant(variant)?;
+        serializer.written.insert(String::from("variant"));
         Ok(serializer)
     }
 
@@ -554,9 +967,7 @@ mod tests {
     #[test]
     fn boolean() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("bool"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("bool"));
         true.serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("bool")), "true");
         false.serialize(serializer.clone()).unwrap();
@@ -566,9 +977,7 @@ mod tests {
     #[test]
     fn numbers() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("n"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("n"));
         (-1i8).serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("n")), "-1");
         (1u8).serialize(serializer.clone()).unwrap();
@@ -592,11 +1001,134 @@ mod tests {
     }
 
     #[test]
-    fn char() {
+    fn durability() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        for durability in &[Durability::None, Durability::Rename, Durability::Sync] {
+            let serializer = FilesystemSerializer::with_options(tmp.path().join("struct"), SerializerOptions::new().durability(*durability));
+            Struct {
+                test: 100,
+                passed: 2100,
+            }.serialize(serializer.clone()).unwrap();
+            assert_eq!(file_to_string(tmp.path().join("struct").join("test")), "100");
+            assert_eq!(file_to_string(tmp.path().join("struct").join("passed")), "2100");
+            assert_eq!(fs::read_dir(tmp.path().join("struct")).unwrap().count(), 2);
+        }
+    }
+
+    #[test]
+    fn durability_replaces_dir() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("c"),
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("n"), SerializerOptions::new().durability(Durability::Sync));
+        vec![1, 2].serialize(serializer.clone()).unwrap();
+        100.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("n")), "100");
+    }
+
+    #[test]
+    fn durability_removes_temporary_file() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::create_dir_all(tmp.path().join("n").join("0")).unwrap();
+        let backend = StdFs::new(&SerializerOptions::new().durability(Durability::Rename));
+        assert!(backend.write(&tmp.path().join("n"), b"100").is_err());
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn trailing_newline() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("var"), SerializerOptions::new().trailing_newline(true));
+        VariantNewType::C(100).serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("var").join("variant")), "C\n");
+        assert_eq!(file_to_string(tmp.path().join("var").join("value")), "100\n");
+        ().serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("var")), "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let options = SerializerOptions::new().file_mode(0o600).dir_mode(0o700);
+        for durability in &[Durability::None, Durability::Rename] {
+            let serializer = FilesystemSerializer::with_options(tmp.path().join("struct"), options.clone().durability(*durability));
+            Struct {
+                test: 100,
+                passed: 2100,
+            }.serialize(serializer.clone()).unwrap();
+            let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&tmp.path().join("struct")), 0o700);
+            assert_eq!(mode(&tmp.path().join("struct").join("test")), 0o600);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions_of_existing() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let s = Struct {
+            test: 100,
+            passed: 2100,
         };
+        s.serialize(FilesystemSerializer::new(tmp.path().join("struct"))).unwrap();
+        let options = SerializerOptions::new().file_mode(0o600).dir_mode(0o700).skip_unchanged(true);
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("struct"), options);
+        s.serialize(serializer.clone()).unwrap();
+        assert!(serializer.changes().is_empty());
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&tmp.path().join("struct")), 0o700);
+        assert_eq!(mode(&tmp.path().join("struct").join("test")), 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions_of_parents() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("a").join("b").join("struct");
+        let serializer = FilesystemSerializer::with_options(&path, SerializerOptions::new().dir_mode(0o700));
+        Struct {
+            test: 100,
+            passed: 2100,
+        }.serialize(serializer.clone()).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&tmp.path().join("a")), 0o700);
+        assert_eq!(mode(&tmp.path().join("a").join("b")), 0o700);
+        assert_eq!(mode(&path), 0o700);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions_outside_root() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::create_dir(tmp.path().join("a")).unwrap();
+        fs::set_permissions(tmp.path().join("a"), fs::Permissions::from_mode(0o755)).unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("a").join("n"), SerializerOptions::new().dir_mode(0o700));
+        100.serialize(serializer.clone()).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&tmp.path().join("a")), 0o755);
+        assert_eq!(file_to_string(tmp.path().join("a").join("n")), "100");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn durability_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::write(tmp.path().join("n"), "1").unwrap();
+        fs::set_permissions(tmp.path().join("n"), fs::Permissions::from_mode(0o640)).unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("n"), SerializerOptions::new().durability(Durability::Rename));
+        100.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("n")), "100");
+        assert_eq!(fs::metadata(tmp.path().join("n")).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+
+    #[test]
+    fn char() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("c"));
         '!'.serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("c")), "!");
     }
@@ -604,9 +1136,7 @@ mod tests {
     #[test]
     fn string() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("str"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("str"));
         "hello".serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("str")), "hello");
     }
@@ -614,9 +1144,7 @@ mod tests {
     #[test]
     fn bytes() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("bytes"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("bytes"));
         use serde_bytes::Bytes;
         Bytes::new(b"hello").serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("bytes")), "hello");
@@ -625,9 +1153,7 @@ mod tests {
     #[test]
     fn option() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("option"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("option"));
         Some("hello").serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("option")), "hello");
         None::<Option<&str>>.serialize(serializer.clone()).unwrap();
@@ -637,9 +1163,7 @@ mod tests {
     #[test]
     fn unit() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("unit"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("unit"));
         ().serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("unit")), "");
     }
@@ -650,9 +1174,7 @@ mod tests {
     #[test]
     fn unit_struct() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("unit"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("unit"));
         UnitStruct.serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("unit")), "");
     }
@@ -668,9 +1190,7 @@ mod tests {
     #[test]
     fn unit_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("unit"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("unit"));
         UnitVariant::B.serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("unit")), "b");
     }
@@ -683,9 +1203,7 @@ mod tests {
     #[test]
     fn tuple_newtype_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("var"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("var"));
         VariantNewType::C(100).serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("var").join("variant")), "C");
         assert_eq!(file_to_string(tmp.path().join("var").join("value")), "100");
@@ -694,11 +1212,9 @@ mod tests {
     #[test]
     fn tuple_newtype_variant_keep_aux_files() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("var"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("var"));
         fs::create_dir_all(tmp.path().join("var")).unwrap();
-        write_to_file(tmp.path().join("var").join("README.md"), "Hello!").unwrap();
+        fs::write(tmp.path().join("var").join("README.md"), "Hello!").unwrap();
         VariantNewType::C(100).serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("var").join("variant")), "C");
         assert_eq!(file_to_string(tmp.path().join("var").join("value")), "100");
@@ -714,9 +1230,7 @@ mod tests {
     #[test]
     fn tuple_newtype_variant_tag() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("var"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("var"));
         VariantNewTypeTag::C(100).serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("var").join("type")), "C");
         assert_eq!(file_to_string(tmp.path().join("var").join("content")), "100");
@@ -728,9 +1242,7 @@ mod tests {
     #[test]
     fn newtype_struct() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("newtype"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("newtype"));
         NewTypeStruct(100).serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("newtype")), "100");
     }
@@ -741,9 +1253,7 @@ mod tests {
     #[test]
     fn newtype_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("newtype"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("newtype"));
         NewTypeVariant(UnitVariant::B).serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("newtype")), "b");
     }
@@ -754,9 +1264,7 @@ mod tests {
     #[test]
     fn newtype_tuple_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("newtype"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("newtype"));
         let v = NewTypeTupleVariant(TupleVariant::V1(100, 100, 100));
         v.serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("newtype").join("variant")), "V1");
@@ -768,9 +1276,7 @@ mod tests {
     #[test]
     fn seq() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("seq"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("seq"));
         vec![100,200,300].serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("seq").join("0")), "100");
         assert_eq!(file_to_string(tmp.path().join("seq").join("1")), "200");
@@ -780,11 +1286,9 @@ mod tests {
     #[test]
     fn seq_keep_aux_files() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("seq"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("seq"));
         fs::create_dir_all(tmp.path().join("seq")).unwrap();
-        write_to_file(tmp.path().join("seq").join("README.md"), "Hello!").unwrap();
+        fs::write(tmp.path().join("seq").join("README.md"), "Hello!").unwrap();
         vec![100,200,300].serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("seq").join("README.md")), "Hello!");
     }
@@ -792,9 +1296,7 @@ mod tests {
     #[test]
     fn seq_empty() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("seq"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("seq"));
         Vec::<u8>::new().serialize(serializer.clone()).unwrap();
         assert!(tmp.path().join("seq").is_dir());
     }
@@ -802,9 +1304,7 @@ mod tests {
     #[test]
     fn seq_shrink() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("seq"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("seq"));
         vec![100,200,300].serialize(serializer.clone()).unwrap();
         vec![100,200].serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("seq").join("0")), "100");
@@ -816,9 +1316,7 @@ mod tests {
     #[test]
     fn tuple() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("tuple"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("tuple"));
         (100,200,300).serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("tuple").join("0")), "100");
         assert_eq!(file_to_string(tmp.path().join("tuple").join("1")), "200");
@@ -832,9 +1330,7 @@ mod tests {
     #[test]
     fn tuple_struct() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("tuple"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("tuple"));
         TupleStruct(100,200,100).serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("tuple").join("0")), "100");
         assert_eq!(file_to_string(tmp.path().join("tuple").join("1")), "200");
@@ -849,9 +1345,7 @@ mod tests {
     #[test]
     fn tuple_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("tuple"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("tuple"));
         TupleVariant::V1(100,200,100).serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("tuple").join("variant")), "V1");
         assert_eq!(file_to_string(tmp.path().join("tuple").join("0")), "100");
@@ -862,9 +1356,7 @@ mod tests {
     #[test]
     fn map() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("map"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("map"));
         use std::collections::HashMap;
         let mut map = HashMap::new();
         map.insert("test", 100);
@@ -877,11 +1369,9 @@ mod tests {
     #[test]
     fn map_keep_aux_files() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("map"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("map"));
         fs::create_dir_all(tmp.path().join("map")).unwrap();
-        write_to_file(tmp.path().join("map").join("README.md"), "Hello!").unwrap();
+        fs::write(tmp.path().join("map").join("README.md"), "Hello!").unwrap();
         vec![100,200,300].serialize(serializer.clone()).unwrap();
         assert_eq!(file_to_string(tmp.path().join("map").join("README.md")), "Hello!");
     }
@@ -890,9 +1380,7 @@ mod tests {
     #[test]
     fn map_extra() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("map"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("map"));
         use std::collections::HashMap;
         let mut map = HashMap::new();
         map.insert("test", 100);
@@ -904,6 +1392,162 @@ mod tests {
         assert!(!tmp.path().join("passed").exists());
     }
 
+    #[test]
+    fn map_prune() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("map"), SerializerOptions::new().prune(Prune::Exact(vec![])));
+        use std::collections::HashMap;
+        let mut map = HashMap::new();
+        map.insert("test", 100);
+        map.insert("passed", 2100);
+        map.serialize(serializer.clone()).unwrap();
+        map.remove("passed");
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("map").join("test")), "100");
+        assert!(!tmp.path().join("map").join("passed").exists());
+    }
+
+    #[test]
+    fn map_prune_keep_aux_files() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("map"), SerializerOptions::new().prune(Prune::Exact(vec!["README.md".into()])));
+        fs::create_dir_all(tmp.path().join("map").join("stale")).unwrap();
+        fs::write(tmp.path().join("map").join("README.md"), "Hello!").unwrap();
+        use std::collections::HashMap;
+        let mut map = HashMap::new();
+        map.insert("test", 100);
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("map").join("README.md")), "Hello!");
+        assert!(!tmp.path().join("map").join("stale").exists());
+    }
+
+    #[test]
+    fn map_key_traversal() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("map"));
+        use std::collections::HashMap;
+        let mut map = HashMap::new();
+        map.insert("../escaped", 1);
+        assert_matches!(map.serialize(serializer.clone()), Err(Error::PathTraversal(_)));
+        assert!(!tmp.path().join("escaped").exists());
+    }
+
+    #[test]
+    fn map_key_current_dir() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::create_dir_all(tmp.path().join("map")).unwrap();
+        fs::write(tmp.path().join("map").join("precious"), "1").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("map"));
+        use std::collections::HashMap;
+        for key in &[".", "./precious", "a/b"] {
+            let mut map = HashMap::new();
+            map.insert(*key, 1);
+            assert_matches!(map.serialize(serializer.clone()), Err(Error::PathTraversal(_)));
+            assert_eq!(file_to_string(tmp.path().join("map").join("precious")), "1");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_traversal() {
+        use std::os::unix::fs::symlink;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        fs::create_dir_all(tmp.path().join("struct")).unwrap();
+        fs::create_dir_all(tmp.path().join("other")).unwrap();
+        symlink(tmp.path().join("other"), tmp.path().join("struct").join("test")).unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
+        let s = Struct { test: 1, passed: 2 };
+        assert_matches!(s.serialize(serializer.clone()), Err(Error::PathTraversal(_)));
+        assert!(tmp.path().join("other").is_dir());
+    }
+
+    #[test]
+    fn seq_shrink_complex_elem() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("seq"));
+        vec![Struct { test: 1, passed: 2 }, Struct { test: 3, passed: 4 }].serialize(serializer.clone()).unwrap();
+        vec![Struct { test: 5, passed: 6 }].serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("seq").join("0").join("test")), "5");
+        assert!(!tmp.path().join("seq").join("1").exists());
+    }
+
+    #[test]
+    fn seq_prune() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("seq"), SerializerOptions::new().prune(Prune::Exact(vec!["README.md".into()])));
+        fs::create_dir_all(tmp.path().join("seq")).unwrap();
+        fs::write(tmp.path().join("seq").join("README.md"), "Hello!").unwrap();
+        fs::write(tmp.path().join("seq").join("stale"), "").unwrap();
+        vec![100,200,300].serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("seq").join("README.md")), "Hello!");
+        assert!(!tmp.path().join("seq").join("stale").exists());
+        assert_eq!(fs::read_dir(tmp.path().join("seq")).unwrap().count(), 4);
+    }
+
+    #[test]
+    fn prune_keeps_paths() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let options = SerializerOptions::new().prune(Prune::Exact(vec!["README.md".into(), "map/NOTES".into()]));
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("root"), options);
+        fs::create_dir_all(tmp.path().join("root").join("map")).unwrap();
+        fs::write(tmp.path().join("root").join("README.md"), "Hello!").unwrap();
+        fs::write(tmp.path().join("root").join("map").join("README.md"), "Hello!").unwrap();
+        fs::write(tmp.path().join("root").join("map").join("NOTES"), "Hello!").unwrap();
+        use std::collections::BTreeMap;
+        let mut map = BTreeMap::new();
+        map.insert("map", BTreeMap::<String, u8>::new());
+        map.serialize(serializer.clone()).unwrap();
+        assert!(tmp.path().join("root").join("README.md").exists());
+        assert!(tmp.path().join("root").join("map").join("NOTES").exists());
+        assert!(!tmp.path().join("root").join("map").join("README.md").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn seq_shrink_keeps_elements() {
+        use std::os::unix::fs::MetadataExt;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("seq"));
+        vec![vec![1], vec![2], vec![3]].serialize(serializer.clone()).unwrap();
+        let inode = |path: &Path| fs::metadata(path).unwrap().ino();
+        let (first, first_element) = (inode(&tmp.path().join("seq/0")), inode(&tmp.path().join("seq/0/0")));
+        vec![vec![1]].serialize(serializer.clone()).unwrap();
+        assert_eq!(inode(&tmp.path().join("seq/0")), first);
+        assert_eq!(inode(&tmp.path().join("seq/0/0")), first_element);
+        assert!(!tmp.path().join("seq/1").exists());
+        assert!(!tmp.path().join("seq/2").exists());
+    }
+
+    #[derive(Serialize)]
+    struct Common {
+        name: String,
+        enabled: bool,
+    }
+
+    #[derive(Serialize)]
+    struct Flattened {
+        port: u16,
+        #[serde(flatten)]
+        common: Common,
+        #[serde(flatten)]
+        extra: ::std::collections::BTreeMap<String, u8>,
+    }
+
+    #[test]
+    fn structure_flatten() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
+        let mut extra = ::std::collections::BTreeMap::new();
+        extra.insert(String::from("level"), 3);
+        let s = Flattened { port: 80, common: Common { name: "web".into(), enabled: true }, extra };
+        s.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("struct").join("port")), "80");
+        assert_eq!(file_to_string(tmp.path().join("struct").join("name")), "web");
+        assert_eq!(file_to_string(tmp.path().join("struct").join("enabled")), "true");
+        assert_eq!(file_to_string(tmp.path().join("struct").join("level")), "3");
+        assert_eq!(fs::read_dir(tmp.path().join("struct")).unwrap().count(), 4);
+        assert!(!Path::new("port").exists());
+    }
 
     macro_rules! key_must_be_a_string_check {
         ($ty: ident, $serializer: expr) => {{
@@ -918,21 +1562,46 @@ mod tests {
     #[test]
     fn map_invalid_key() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("map"),
-        };
-        key_must_be_a_string_check!(i8, serializer);
-        key_must_be_a_string_check!(u8, serializer);
-        key_must_be_a_string_check!(i16, serializer);
-        key_must_be_a_string_check!(u16, serializer);
-        key_must_be_a_string_check!(i32, serializer);
-        key_must_be_a_string_check!(u32, serializer);
-        key_must_be_a_string_check!(i64, serializer);
-        key_must_be_a_string_check!(u64, serializer);
-        key_must_be_a_string_check!(bool, serializer);
+        let serializer = FilesystemSerializer::new(tmp.path().join("map"));
         key_must_be_a_string_check!(Struct, serializer);
     }
 
+    #[derive(Serialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    enum Key {
+        First,
+        Second,
+    }
+
+    #[test]
+    fn map_non_string_keys() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("map"));
+        use std::collections::BTreeMap;
+        let mut map = BTreeMap::new();
+        map.insert(1u32, 100);
+        map.insert(2100, 200);
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("map").join("1")), "100");
+        assert_eq!(file_to_string(tmp.path().join("map").join("2100")), "200");
+        let mut map = BTreeMap::new();
+        map.insert(-1i8, 1);
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("map").join("-1")), "1");
+        let mut map = BTreeMap::new();
+        map.insert(Key::First, 1);
+        map.insert(Key::Second, 2);
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("map").join("Second")), "2");
+        let mut map = BTreeMap::new();
+        map.insert('x', 1);
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("map").join("x")), "1");
+        let mut map = BTreeMap::new();
+        map.insert(true, 1);
+        map.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("map").join("true")), "1");
+    }
+
     #[derive(Serialize, PartialEq, Eq, Hash, Default)]
     struct Struct {
         test: u8,
@@ -942,9 +1611,7 @@ mod tests {
     #[test]
     fn structure() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("struct"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
         Struct {
             test: 100,
             passed: 2100,
@@ -956,11 +1623,9 @@ mod tests {
     #[test]
     fn structure_keep_aux_files() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("struct"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
         fs::create_dir_all(tmp.path().join("struct")).unwrap();
-        write_to_file(tmp.path().join("struct").join("README.md"), "Hello!").unwrap();
+        fs::write(tmp.path().join("struct").join("README.md"), "Hello!").unwrap();
         Struct {
             test: 100,
             passed: 2100,
@@ -979,9 +1644,7 @@ mod tests {
     #[test]
     fn struct_variant() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("struct"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
         StructVariant::V1 {
             test: 100,
             passed: 2100,
@@ -991,6 +1654,146 @@ mod tests {
         assert_eq!(file_to_string(tmp.path().join("struct").join("passed")), "2100");
     }
 
+    #[derive(Serialize)]
+    struct OptionalStruct {
+        test: u8,
+        passed: Option<u64>,
+    }
+
+    #[test]
+    fn structure_prune() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("struct"), SerializerOptions::new().prune(Prune::Exact(vec![])));
+        Struct {
+            test: 100,
+            passed: 2100,
+        }.serialize(serializer.clone()).unwrap();
+        fs::write(tmp.path().join("struct").join("extra"), "1").unwrap();
+        OptionalStruct {
+            test: 100,
+            passed: None,
+        }.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("struct").join("test")), "100");
+        assert!(!tmp.path().join("struct").join("passed").exists());
+        assert!(!tmp.path().join("struct").join("extra").exists());
+    }
+
+    #[test]
+    fn variant_switch_prune() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("var"), SerializerOptions::new().prune(Prune::Exact(vec![])));
+        StructVariant::V1 {
+            test: 100,
+            passed: 2100,
+        }.serialize(serializer.clone()).unwrap();
+        VariantNewType::C(100).serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("var").join("variant")), "C");
+        assert_eq!(file_to_string(tmp.path().join("var").join("value")), "100");
+        assert_eq!(fs::read_dir(tmp.path().join("var")).unwrap().count(), 2);
+        TupleVariant::V1(1, 2, 3).serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("var").join("variant")), "V1");
+        assert_eq!(fs::read_dir(tmp.path().join("var")).unwrap().count(), 4);
+    }
+
+    #[derive(Serialize)]
+    enum Mode {
+        Plain(String),
+        Tls { cert: String },
+    }
+
+    #[test]
+    fn variant_switch() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("var"));
+        Mode::Plain("http".into()).serialize(serializer.clone()).unwrap();
+        fs::write(tmp.path().join("var").join("README.md"), "Hello!").unwrap();
+        Mode::Tls { cert: "cert.pem".into() }.serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("var").join("variant")), "Tls");
+        assert_eq!(file_to_string(tmp.path().join("var").join("cert")), "cert.pem");
+        assert_eq!(file_to_string(tmp.path().join("var").join("README.md")), "Hello!");
+        assert!(!tmp.path().join("var").join("value").exists());
+        TupleVariant::V1(1, 2, 3).serialize(serializer.clone()).unwrap();
+        VariantNewType::C(100).serialize(serializer.clone()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("var").join("value")), "100");
+        assert!(!tmp.path().join("var").join("0").exists());
+        // Fields of the struct variant are left to `Prune`
+        assert_eq!(file_to_string(tmp.path().join("var").join("cert")), "cert.pem");
+        assert_eq!(file_to_string(tmp.path().join("var").join("README.md")), "Hello!");
+    }
+
+    #[test]
+    fn skip_unchanged() {
+        use std::path::PathBuf;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let options = SerializerOptions::new().skip_unchanged(true);
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("struct"), options.clone());
+        Struct { test: 1, passed: 2 }.serialize(serializer.clone()).unwrap();
+        assert_eq!(serializer.changes(), vec![PathBuf::new(), PathBuf::from("passed"), PathBuf::from("test")]);
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("struct"), options.clone());
+        Struct { test: 1, passed: 2 }.serialize(serializer.clone()).unwrap();
+        assert!(serializer.changes().is_empty());
+        Struct { test: 1, passed: 3 }.serialize(serializer.clone()).unwrap();
+        assert_eq!(serializer.changes(), vec![PathBuf::from("passed")]);
+        assert_eq!(file_to_string(tmp.path().join("struct").join("passed")), "3");
+    }
+
+    #[test]
+    fn changes_removed() {
+        use std::path::PathBuf;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        vec![1, 2, 3].serialize(FilesystemSerializer::new(tmp.path().join("seq"))).unwrap();
+        let serializer = FilesystemSerializer::with_options(tmp.path().join("seq"), SerializerOptions::new().skip_unchanged(true));
+        vec![1].serialize(serializer.clone()).unwrap();
+        assert_eq!(serializer.changes(), vec![PathBuf::from("1"), PathBuf::from("2")]);
+        assert!(!tmp.path().join("seq").join("1").exists());
+    }
+
+    #[test]
+    fn pointer_set() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("seq"));
+        vec![Struct { test: 1, passed: 2 }, Struct { test: 3, passed: 4 }].serialize(serializer.clone()).unwrap();
+        let untouched = fs::metadata(tmp.path().join("seq").join("1").join("test")).unwrap().modified().unwrap();
+        5u8.serialize(serializer.descend(&["0", "test"]).unwrap()).unwrap();
+        Struct { test: 6, passed: 7 }.serialize(serializer.descend(&["2"]).unwrap()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("seq").join("0").join("test")), "5");
+        assert_eq!(file_to_string(tmp.path().join("seq").join("2").join("test")), "6");
+        assert_eq!(fs::metadata(tmp.path().join("seq").join("1").join("test")).unwrap().modified().unwrap(), untouched);
+        assert_matches!(serializer.descend(&["4"]).err(), Some(Error::InvalidIndex(ref pointer)) if pointer == "4");
+        assert_matches!(serializer.descend(&["01"]).err(), Some(Error::InvalidIndex(_)));
+        assert_matches!(serializer.descend(&["0", "..", ".."]).err(), Some(Error::PathTraversal(_)));
+    }
+
+    #[test]
+    fn pointer_remove() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("seq"));
+        vec![Struct { test: 1, passed: 2 }, Struct { test: 3, passed: 4 }, Struct { test: 5, passed: 6 }].serialize(serializer.clone()).unwrap();
+        serializer.remove_at(&["0", "passed"]).unwrap();
+        assert!(!tmp.path().join("seq").join("0").join("passed").exists());
+        serializer.remove_at(&["1"]).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("seq").join("1").join("test")), "5");
+        assert!(!tmp.path().join("seq").join("2").exists());
+        assert_matches!(serializer.remove_at(&["2", "test"]), Err(Error::MissingSegment(ref pointer)) if pointer == "2");
+        assert_matches!(serializer.remove_at(&["0", "passed"]), Err(Error::MissingSegment(ref pointer)) if pointer == "0/passed");
+        assert_matches!(serializer.remove_at::<&str>(&[]), Err(Error::InvalidPointer(ref pointer)) if pointer.is_empty());
+        assert!(tmp.path().join("seq").join("0").exists());
+        assert_eq!(fs::read_dir(tmp.path().join("seq")).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn pointer_empty_seq() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
+        let mut map = ::std::collections::BTreeMap::new();
+        map.insert("list", Vec::<u8>::new());
+        map.serialize(serializer.clone()).unwrap();
+        assert_matches!(serializer.descend(&["list", "5"]).err(), Some(Error::InvalidIndex(ref pointer)) if pointer == "list/5");
+        assert_matches!(serializer.descend(&["list", "00"]).err(), None);
+        1u8.serialize(serializer.descend(&["list", "0"]).unwrap()).unwrap();
+        assert_eq!(file_to_string(tmp.path().join("struct").join("list").join("0")), "1");
+    }
+
     #[derive(Serialize)]
     #[serde(tag = "type")]
     enum StructVariantTag {
@@ -1003,9 +1806,7 @@ mod tests {
     #[test]
     fn struct_variant_tag() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("struct"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
         StructVariantTag::V1 {
             test: 100,
             passed: 2100,
@@ -1027,9 +1828,7 @@ mod tests {
     #[test]
     fn struct_variant_tag_content() {
         let tmp = TempDir::new("serde-fs").unwrap();
-        let serializer = FilesystemSerializer {
-            path: tmp.path().join("struct"),
-        };
+        let serializer = FilesystemSerializer::new(tmp.path().join("struct"));
         StructVariantTagContent::V1 {
             test: 100,
             passed: 2100,
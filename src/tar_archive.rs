@@ -0,0 +1,137 @@
+//! Trees in tar archives
+
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tar::{Archive, Builder, EntryType, Header};
+
+use backend::{self, Backend};
+use mem::MemFs;
+use ser::{self, FilesystemSerializer, SerializerOptions};
+use de::{self, FilesystemDeserializer, DeserializerOptions};
+
+/// Path of an archive entry (always relative, as archives can't hold others)
+fn entry_path(path: &Path) -> PathBuf {
+    path.components().filter(|component| matches!(*component, Component::Normal(_))).collect()
+}
+
+/// Serializes `value` into memory and appends the tree to `builder` under `path`
+pub(crate) fn write<W, T, P>(builder: &mut Builder<W>, path: P, value: &T, options: &SerializerOptions) -> Result<(), ser::Error>
+    where W: Write, T: ?Sized + Serialize, P: AsRef<Path> {
+    let fs = Arc::new(MemFs::new());
+    value.serialize(FilesystemSerializer::with_backend(path.as_ref(), options.clone(), fs.clone()))?;
+    backend::walk(&*fs, path.as_ref(), &mut |path, content| {
+        let path = entry_path(path);
+        if path == Path::new("") {
+            return Ok(());
+        }
+        let mut header = Header::new_gnu();
+        // Archives don't depend on when they were created
+        header.set_mtime(0);
+        match content {
+            Some(content) => {
+                header.set_entry_type(EntryType::Regular);
+                header.set_mode(options.file_mode.unwrap_or(0o644));
+                header.set_size(content.len() as u64);
+                builder.append_data(&mut header, path, &content[..])
+            },
+            None => {
+                header.set_entry_type(EntryType::Directory);
+                header.set_mode(options.dir_mode.unwrap_or(0o755));
+                header.set_size(0);
+                builder.append_data(&mut header, path, io::empty())
+            },
+        }
+    })?;
+    Ok(())
+}
+
+/// Reads the files and directories of a tar stream into memory and
+/// deserializes the tree at `path`
+///
+/// Other kinds of entries (like symbolic links) are ignored.
+pub(crate) fn read<R, T, P>(reader: R, path: P, options: &DeserializerOptions) -> Result<T, de::Error>
+    where R: Read, T: DeserializeOwned, P: AsRef<Path> {
+    let fs = Arc::new(MemFs::new());
+    for entry in Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+        match entry.header().entry_type() {
+            EntryType::Directory => fs.create_dir(&entry_path)?,
+            EntryType::Regular => {
+                let mut content = vec![];
+                entry.read_to_end(&mut content)?;
+                if let Some(parent) = entry_path.parent() {
+                    fs.create_dir(parent)?;
+                }
+                fs.write(&entry_path, &content)?;
+            },
+            _ => (),
+        }
+    }
+    T::deserialize(FilesystemDeserializer::with_backend(entry_path(path.as_ref()), options.clone(), fs))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Server {
+        host: String,
+        ports: Vec<u16>,
+        backup: Option<String>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let server = Server { host: "localhost".into(), ports: vec![80, 443], backup: None };
+        let mut builder = Builder::new(vec![]);
+        write(&mut builder, "server", &server, &SerializerOptions::new()).unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        let mut entries = vec![];
+        for entry in Archive::new(&archive[..]).entries().unwrap() {
+            let entry = entry.unwrap();
+            entries.push((entry.path().unwrap().to_string_lossy().into_owned(), entry.header().mode().unwrap()));
+        }
+        assert_eq!(entries, vec![
+            (String::from("server"), 0o755),
+            (String::from("server/host"), 0o644),
+            (String::from("server/ports"), 0o755),
+            (String::from("server/ports/0"), 0o644),
+            (String::from("server/ports/1"), 0o644),
+        ]);
+
+        let read: Server = read(&archive[..], "server", &DeserializerOptions::new()).unwrap();
+        assert_eq!(read, server);
+    }
+
+    #[test]
+    fn absolute_path() {
+        let server = Server { host: "localhost".into(), ports: vec![80], backup: None };
+        let mut builder = Builder::new(vec![]);
+        write(&mut builder, "/srv/server", &server, &SerializerOptions::new()).unwrap();
+        let archive = builder.into_inner().unwrap();
+        let paths: Vec<_> = Archive::new(&archive[..]).entries().unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(paths, vec!["srv/server", "srv/server/host", "srv/server/ports", "srv/server/ports/0"]);
+        let read: Server = read(&archive[..], "/srv/server", &DeserializerOptions::new()).unwrap();
+        assert_eq!(read, server);
+    }
+
+    #[test]
+    fn leaf() {
+        let mut builder = Builder::new(vec![]);
+        write(&mut builder, "port", &8080, &SerializerOptions::new().file_mode(0o600)).unwrap();
+        let archive = builder.into_inner().unwrap();
+        let port: u16 = read(&archive[..], "./port", &DeserializerOptions::new()).unwrap();
+        assert_eq!(port, 8080);
+    }
+
+}
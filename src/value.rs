@@ -0,0 +1,418 @@
+//! Dynamically typed representation of a file system tree
+
+use std::io;
+use std::fmt;
+use std::ops::Index;
+use std::path::{Component, Path};
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::de::{self, Visitor};
+
+use confine::Root;
+use backend::{Backend, Kind, StdFs};
+use pointer;
+use diff::{self, Change};
+use de::{Error, is_seq_names};
+
+/// Any file system tree
+///
+/// | Variant   | File system                                        |
+/// |-----------|----------------------------------------------------|
+/// | `Missing` | Nothing at the path                                |
+/// | `File`    | File with given contents                           |
+/// | `Dir`     | Directory with entries called after the map's keys |
+/// | `Seq`     | Directory with entries called `0`, `1`, ..         |
+///
+/// Trees don't always read back as they were written: an empty `Seq` is read
+/// as an empty `Dir`, a `Dir` with entries `0`..`n` as a `Seq`, and a `Seq`
+/// containing `Missing` elements leaves gaps in the numbering, so it is read
+/// as a `Dir`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Value {
+    #[default]
+    Missing,
+    File(Vec<u8>),
+    Dir(BTreeMap<String, Value>),
+    Seq(Vec<Value>),
+}
+
+static MISSING: Value = Value::Missing;
+
+impl Value {
+    pub fn is_missing(&self) -> bool {
+        *self == Value::Missing
+    }
+
+    /// File contents, if this is a file
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Value::File(ref bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// File contents, if this is a file containing valid UTF-8
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_bytes().and_then(|bytes| ::std::str::from_utf8(bytes).ok())
+    }
+
+    /// Looks up a descendant by a pointer (like `servers/0/port`)
+    ///
+    /// Returns `Missing` if any of the pointer's segments doesn't exist
+    /// or the pointer is invalid.
+    pub fn at(&self, pointer: &str) -> &Value {
+        match pointer::parse(pointer) {
+            Some(segments) => segments.iter().fold(self, |value, segment| value.child(segment)),
+            None => &MISSING,
+        }
+    }
+
+    /// Compares this tree with a `new` one
+    ///
+    /// Directories (and sequences) are compared entry by entry, down to the
+    /// smallest differing subtrees. Entries of different kinds, as well as
+    /// directories with different `variant` files (enums that switched
+    /// variants), are reported as changed as a whole.
+    pub fn diff(&self, new: &Value) -> Vec<Change> {
+        diff::diff(self, new)
+    }
+
+    pub(crate) fn child(&self, name: &str) -> &Value {
+        match *self {
+            Value::Dir(ref map) => map.get(name).unwrap_or(&MISSING),
+            Value::Seq(ref seq) => name.parse::<usize>().ok()
+                .and_then(|index| seq.get(index)).unwrap_or(&MISSING),
+            _ => &MISSING,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &'a str) -> Self {
+        Value::File(s.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::File(s.into_bytes())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(bytes: Vec<u8>) -> Self {
+        Value::File(bytes)
+    }
+}
+
+impl<'a> Index<&'a str> for Value {
+    type Output = Value;
+
+    /// Returns an entry of a directory (or `Missing`)
+    fn index(&self, name: &'a str) -> &Value {
+        self.child(name)
+    }
+}
+
+impl Index<usize> for Value {
+    type Output = Value;
+
+    /// Returns an element of a sequence (or `Missing`)
+    fn index(&self, index: usize) -> &Value {
+        match *self {
+            Value::Seq(ref seq) => seq.get(index).unwrap_or(&MISSING),
+            _ => &MISSING,
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Value::Missing => serializer.serialize_none(),
+            Value::File(ref bytes) => match ::std::str::from_utf8(bytes) {
+                Ok(s) => serializer.serialize_str(s),
+                Err(_) => serializer.serialize_bytes(bytes),
+            },
+            Value::Dir(ref map) => {
+                let mut s = serializer.serialize_map(Some(map.len()))?;
+                for (key, value) in map {
+                    s.serialize_entry(key, value)?;
+                }
+                s.end()
+            },
+            Value::Seq(ref seq) => {
+                let mut s = serializer.serialize_seq(Some(seq.len()))?;
+                for value in seq {
+                    s.serialize_element(value)?;
+                }
+                s.end()
+            },
+        }
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::from(v.to_string()))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::from(v.to_string()))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::from(v.to_string()))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::from(v.to_string()))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::File(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::File(v))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::File(vec![]))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Missing)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut access: A) -> Result<Value, A::Error> {
+        let mut seq = vec![];
+        while let Some(value) = access.next_element()? {
+            seq.push(value);
+        }
+        Ok(Value::Seq(seq))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut access: A) -> Result<Value, A::Error> {
+        let mut map = BTreeMap::new();
+        while let Some((key, value)) = access.next_entry()? {
+            map.insert(key, value);
+        }
+        Ok(Value::Dir(map))
+    }
+}
+
+/// Booleans and numbers of other formats become files holding their textual
+/// form, so formatting details (like `1.50` or `+5`) are lost. Unit (like JSON's
+/// `null`) becomes an empty file.
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+/// Reads a tree as is (file contents aren't interpreted in any way)
+pub(crate) fn read<P: AsRef<Path>>(path: P) -> Result<Value, Error> {
+    read_from(&StdFs::default(), path.as_ref())
+}
+
+/// Trees can be read from like from a file system (relative to an empty path),
+/// but not modified
+impl Backend for Value {
+    fn kind(&self, path: &Path) -> Result<Option<Kind>, io::Error> {
+        Ok(match *self.lookup(path) {
+            Value::Missing => None,
+            Value::File(_) => Some(Kind::File),
+            Value::Dir(_) | Value::Seq(_) => Some(Kind::Dir),
+        })
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<String>, io::Error> {
+        match *self.lookup(path) {
+            Value::Dir(ref map) => Ok(map.keys().cloned().collect()),
+            Value::Seq(ref seq) => Ok((0..seq.len()).map(|index| index.to_string()).collect()),
+            _ => Err(not_found(path)),
+        }
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, io::Error> {
+        match *self.lookup(path) {
+            Value::File(ref bytes) => Ok(bytes.clone()),
+            _ => Err(not_found(path)),
+        }
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), io::Error> {
+        Err(read_only(path))
+    }
+
+    fn write(&self, path: &Path, _content: &[u8]) -> Result<(), io::Error> {
+        Err(read_only(path))
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), io::Error> {
+        Err(read_only(path))
+    }
+
+    fn rename(&self, from: &Path, _to: &Path) -> Result<(), io::Error> {
+        Err(read_only(from))
+    }
+}
+
+impl Value {
+    fn lookup(&self, path: &Path) -> &Value {
+        path.components().fold(self, |value, component| match component {
+            Component::Normal(name) => value.child(&name.to_string_lossy()),
+            Component::CurDir => value,
+            _ => &MISSING,
+        })
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display()))
+}
+
+fn read_only(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is read-only", path.display()))
+}
+
+/// Reads a tree from a backend, without leaving `path` (see `read`)
+pub(crate) fn read_from(backend: &dyn Backend, path: &Path) -> Result<Value, Error> {
+    let root = Root::new(path, backend);
+    read_within(backend, &root, path)
+}
+
+fn read_within(backend: &dyn Backend, root: &Root, path: &Path) -> Result<Value, Error> {
+    if !root.contains(path, backend)? {
+        return Err(Error::PathTraversal(path.to_path_buf()));
+    }
+    match backend.kind(path)? {
+        None => Ok(Value::Missing),
+        Some(Kind::File) => Ok(Value::File(backend.read(path)?)),
+        Some(Kind::Dir) => {
+            let names = backend.read_dir(path)?;
+            if is_seq_names(&names) {
+                let seq = (0..names.len()).map(|index| read_within(backend, root, &path.join(index.to_string())));
+                return Ok(Value::Seq(seq.collect::<Result<_, _>>()?));
+            }
+            let mut map = BTreeMap::new();
+            for name in names {
+                let value = read_within(backend, root, &path.join(&name))?;
+                map.insert(name, value);
+            }
+            Ok(Value::Dir(map))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use super::*;
+    use tempdir::TempDir;
+
+    fn tree() -> Value {
+        let mut server = BTreeMap::new();
+        server.insert(String::from("host"), Value::from("localhost"));
+        server.insert(String::from("port"), Value::from("8080"));
+        let mut map = BTreeMap::new();
+        map.insert(String::from("servers"), Value::Seq(vec![Value::Dir(server)]));
+        map.insert(String::from("binary"), Value::File(vec![0xff, 0]));
+        map.insert(String::from("empty"), Value::File(vec![]));
+        map.insert(String::from("nothing"), Value::Dir(BTreeMap::new()));
+        Value::Dir(map)
+    }
+
+    #[test]
+    fn round_trip() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("tree");
+        let value = tree();
+        value.serialize(::FilesystemSerializer::new(&path)).unwrap();
+        assert_eq!(fs::read(path.join("servers").join("0").join("port")).unwrap(), b"8080");
+        assert_eq!(read(&path).unwrap(), value);
+    }
+
+    #[test]
+    fn round_trip_ambiguities() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("tree");
+        let write = |value: &Value| {
+            let _ = fs::remove_dir_all(&path);
+            value.serialize(::FilesystemSerializer::new(&path)).unwrap();
+        };
+        write(&Value::Seq(vec![]));
+        assert_eq!(read(&path).unwrap(), Value::Dir(BTreeMap::new()));
+        let mut map = BTreeMap::new();
+        map.insert(String::from("0"), Value::from("a"));
+        map.insert(String::from("1"), Value::from("b"));
+        write(&Value::Dir(map));
+        assert_eq!(read(&path).unwrap(), Value::Seq(vec![Value::from("a"), Value::from("b")]));
+        write(&Value::Seq(vec![Value::from("a"), Value::Missing, Value::from("b")]));
+        let mut map = BTreeMap::new();
+        map.insert(String::from("0"), Value::from("a"));
+        map.insert(String::from("2"), Value::from("b"));
+        assert_eq!(read(&path).unwrap(), Value::Dir(map));
+    }
+
+    #[test]
+    fn deserialize_other_formats() {
+        let value: Value = ::serde_json::from_str(r#"[1.50, true, null, "080"]"#).unwrap();
+        assert_eq!(value, Value::Seq(vec![Value::from("1.5"), Value::from("true"), Value::from(""), Value::from("080")]));
+    }
+
+    #[test]
+    fn missing() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        assert_eq!(read(tmp.path().join("tree")).unwrap(), Value::Missing);
+    }
+
+    #[test]
+    fn deserialize() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("tree");
+        tree().serialize(::FilesystemSerializer::new(&path)).unwrap();
+        let value = Value::deserialize(::FilesystemDeserializer::new(&path)).unwrap();
+        assert_eq!(value["servers"][0]["host"].as_str(), Some("localhost"));
+        assert_eq!(value["servers"][0]["port"].as_str(), Some("8080"));
+    }
+
+    #[test]
+    fn index() {
+        let value = tree();
+        assert_eq!(value["servers"][0]["port"].as_str(), Some("8080"));
+        assert_eq!(value.at("servers/0/host").as_str(), Some("localhost"));
+        assert_eq!(value.at("/servers/0/host"), &value["servers"][0]["host"]);
+        assert!(value.at("servers/1/host").is_missing());
+        assert!(value["binary"]["test"].is_missing());
+        assert!(value["servers"]["0"]["port"].as_str().is_some());
+        assert_eq!(value.at(""), &value);
+        assert!(value.at("servers//host").is_missing());
+    }
+
+}
@@ -0,0 +1,249 @@
+//! Watching trees for changes
+
+use std::io;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use notify::{self, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as NotifyWatcher};
+use serde::de::DeserializeOwned;
+
+use de::{Error as DeserializerError, DeserializerOptions};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    IoError(io::Error),
+    NotifyError(notify::Error),
+}
+
+/// A tree reloaded after it changed
+#[derive(Debug)]
+pub struct Reload<T> {
+    /// The reloaded value, or why it couldn't be deserialized
+    pub value: Result<T, DeserializerError>,
+    /// Changed paths (relative to the root)
+    ///
+    /// An empty path stands for the root itself (for example, when it was
+    /// replaced by `to_fs_atomic`). Empty if the platform couldn't tell what changed.
+    pub changes: BTreeSet<PathBuf>,
+}
+
+/// Watches a tree and reloads it whenever it changes
+///
+/// Changes are debounced: the tree is reloaded once no more changes have
+/// happened for a while (100ms by default), so that serializing a value
+/// results in a single reload. A tree that keeps changing is still reloaded
+/// ten debounce periods after the first change.
+///
+/// The root's parent directory is watched as well (where it can be), so that
+/// the root keeps being watched after it was replaced (for example, by
+/// `to_fs_atomic`).
+pub struct Watcher<T> {
+    reloads: Receiver<Reload<T>>,
+    _watcher: Arc<Mutex<RecommendedWatcher>>,
+}
+
+impl<T: DeserializeOwned + Send + 'static> Watcher<T> {
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self, Error> {
+        Watcher::with_options(root, DeserializerOptions::default(), Duration::from_millis(100))
+    }
+
+    /// Creates a watcher deserializing with given options, reloading
+    /// once nothing changed for `debounce`
+    pub fn with_options<P: AsRef<Path>>(root: P, options: DeserializerOptions, debounce: Duration) -> Result<Self, Error> {
+        // Events carry resolved paths on some platforms
+        let root = fs::canonicalize(root)?;
+        let (events_sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = events_sender.send(event);
+        })?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+        if let Some(parent) = root.parent() {
+            // Without it, only replacing the root goes unnoticed
+            let _ = watcher.watch(parent, RecursiveMode::NonRecursive);
+        }
+        let watcher = Arc::new(Mutex::new(watcher));
+        // The thread must not keep the watcher alive, see below
+        let rearm = Arc::downgrade(&watcher);
+        let (reloads_sender, reloads) = mpsc::channel();
+        thread::spawn(move || {
+            // Runs until the watcher (and thus the events' sender) is dropped
+            while let Ok(event) = events.recv() {
+                let mut changes = BTreeSet::new();
+                let mut changed = collect(&root, event, &mut changes);
+                // Debounce periods too long to add up have no deadline
+                let deadline = debounce.checked_mul(10).and_then(|max_wait| Instant::now().checked_add(max_wait));
+                loop {
+                    let timeout = match deadline {
+                        Some(deadline) => debounce.min(deadline.saturating_duration_since(Instant::now())),
+                        None => debounce,
+                    };
+                    match events.recv_timeout(timeout) {
+                        Ok(event) => changed |= collect(&root, event, &mut changes),
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                if !changed {
+                    continue;
+                }
+                // The root might have been replaced, in which case the old
+                // one (or nothing) is being watched
+                if changes.contains(Path::new("")) {
+                    if let Some(watcher) = rearm.upgrade() {
+                        if let Ok(mut watcher) = watcher.lock() {
+                            let _ = watcher.unwatch(&root);
+                            let _ = watcher.watch(&root, RecursiveMode::Recursive);
+                        }
+                    }
+                }
+                let value = ::from_fs_with(&root, &options);
+                if reloads_sender.send(Reload { value, changes }).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Watcher { reloads, _watcher: watcher })
+    }
+
+    /// Waits for the next reload
+    pub fn recv(&self) -> Option<Reload<T>> {
+        self.reloads.recv().ok()
+    }
+
+    /// Waits for the next reload for at most `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Reload<T>> {
+        self.reloads.recv_timeout(timeout).ok()
+    }
+
+    /// Returns a reload if there is one already
+    pub fn try_recv(&self) -> Option<Reload<T>> {
+        self.reloads.try_recv().ok()
+    }
+}
+
+/// Adds paths changed by an event, returning whether it changed anything
+fn collect(root: &Path, event: Result<Event, notify::Error>, changes: &mut BTreeSet<PathBuf>) -> bool {
+    let event = match event {
+        Ok(event) => event,
+        // Events might have been lost
+        Err(_) => return true,
+    };
+    // Reading the tree (including reloading it) is not a change
+    if let EventKind::Access(_) = event.kind {
+        return false;
+    }
+    let mut changed = false;
+    for path in event.paths {
+        // Temporary files of `Durability::Rename` and `Durability::Sync`
+        if path.file_name().is_some_and(|name| name.to_string_lossy().ends_with(".serde_fs-tmp")) {
+            continue;
+        }
+        if let Ok(path) = path.strip_prefix(root) {
+            changes.insert(path.to_path_buf());
+            changed = true;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use super::*;
+    use tempdir::TempDir;
+    use ser::{Durability, SerializerOptions};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn reload() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        ::to_fs(tmp.path(), &Server { host: "localhost".into(), port: 80 }).unwrap();
+        let watcher = Watcher::<Server>::new(tmp.path()).unwrap();
+        fs::write(tmp.path().join("port"), "8080").unwrap();
+        let reload = watcher.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(reload.value.unwrap(), Server { host: "localhost".into(), port: 8080 });
+        assert_eq!(reload.changes.into_iter().collect::<Vec<_>>(), vec![PathBuf::from("port")]);
+        assert!(watcher.try_recv().is_none());
+    }
+
+    #[test]
+    fn debounce() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        ::to_fs(tmp.path(), &Server { host: "localhost".into(), port: 80 }).unwrap();
+        let watcher = Watcher::<Server>::with_options(tmp.path(), DeserializerOptions::new(), Duration::from_millis(500)).unwrap();
+        let options = SerializerOptions::new().durability(Durability::Rename);
+        ::to_fs_with(tmp.path(), &Server { host: "example.com".into(), port: 443 }, &options).unwrap();
+        let reload = watcher.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(reload.value.unwrap(), Server { host: "example.com".into(), port: 443 });
+        assert_eq!(reload.changes.into_iter().collect::<Vec<_>>(), vec![PathBuf::from("host"), PathBuf::from("port")]);
+        assert!(watcher.recv_timeout(Duration::from_millis(1000)).is_none());
+    }
+
+    #[test]
+    fn atomic() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let path = tmp.path().join("server");
+        ::to_fs(&path, &Server { host: "localhost".into(), port: 80 }).unwrap();
+        let watcher = Watcher::<Server>::new(&path).unwrap();
+        ::to_fs_atomic(&path, &Server { host: "localhost".into(), port: 8080 }).unwrap();
+        let reload = watcher.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(reload.value.unwrap(), Server { host: "localhost".into(), port: 8080 });
+        assert!(reload.changes.contains(Path::new("")));
+        fs::write(path.join("port"), "443").unwrap();
+        let reload = watcher.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(reload.value.unwrap(), Server { host: "localhost".into(), port: 443 });
+        fs::write(tmp.path().join("unrelated"), "").unwrap();
+        assert!(watcher.recv_timeout(Duration::from_millis(1000)).is_none());
+    }
+
+    #[test]
+    fn max_wait() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        ::to_fs(tmp.path(), &Server { host: "localhost".into(), port: 80 }).unwrap();
+        let watcher = Watcher::<Server>::with_options(tmp.path(), DeserializerOptions::new(), Duration::from_millis(200)).unwrap();
+        let started = Instant::now();
+        let mut port = 0;
+        while started.elapsed() < Duration::from_secs(5) && watcher.try_recv().is_none() {
+            port += 1;
+            fs::write(tmp.path().join("port"), port.to_string()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn long_debounce() {
+        use std::sync::mpsc::TryRecvError;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        ::to_fs(tmp.path(), &Server { host: "localhost".into(), port: 80 }).unwrap();
+        let watcher = Watcher::<Server>::with_options(tmp.path(), DeserializerOptions::new(), Duration::MAX).unwrap();
+        fs::write(tmp.path().join("port"), "8080").unwrap();
+        thread::sleep(Duration::from_millis(500));
+        assert_matches!(watcher.reloads.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn invalid() {
+        let tmp = TempDir::new("serde-fs").unwrap();
+        ::to_fs(tmp.path(), &Server { host: "localhost".into(), port: 80 }).unwrap();
+        let watcher = Watcher::<Server>::new(tmp.path()).unwrap();
+        fs::write(tmp.path().join("port"), "http").unwrap();
+        let reload = watcher.recv_timeout(TIMEOUT).unwrap();
+        assert_matches!(reload.value, Err(DeserializerError::ParseIntError(_)));
+    }
+
+}
@@ -0,0 +1,136 @@
+//! Trees in zip archives
+
+use std::io::{self, Read, Seek, Write};
+use std::path::{Component, Path};
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use zip::{ZipArchive, ZipWriter};
+use zip::write::SimpleFileOptions;
+
+use backend::{self, Backend};
+use mem::MemFs;
+use ser::{self, FilesystemSerializer, SerializerOptions};
+use de::{self, FilesystemDeserializer, DeserializerOptions};
+
+/// Name of an archive entry (always separated with `/`)
+fn entry_name(path: &Path) -> String {
+    let names: Vec<_> = path.components().filter_map(|component| match component {
+        Component::Normal(name) => Some(name.to_string_lossy()),
+        _ => None,
+    }).collect();
+    names.join("/")
+}
+
+/// Serializes `value` into memory and adds the tree to `writer` under `path`
+pub(crate) fn write<W, T, P>(writer: &mut ZipWriter<W>, path: P, value: &T, options: &SerializerOptions) -> Result<(), ser::Error>
+    where W: Write + Seek, T: ?Sized + Serialize, P: AsRef<Path> {
+    let fs = Arc::new(MemFs::new());
+    value.serialize(FilesystemSerializer::with_backend(path.as_ref(), options.clone(), fs.clone()))?;
+    backend::walk(&*fs, path.as_ref(), &mut |path, content| {
+        match content {
+            Some(content) => {
+                let file_options = SimpleFileOptions::default().unix_permissions(options.file_mode.unwrap_or(0o644));
+                writer.start_file(entry_name(path), file_options)?;
+                writer.write_all(&content)
+            },
+            None => {
+                let dir_options = SimpleFileOptions::default().unix_permissions(options.dir_mode.unwrap_or(0o755));
+                writer.add_directory(entry_name(path), dir_options)?;
+                Ok(())
+            },
+        }
+    })?;
+    Ok(())
+}
+
+/// Reads the files and directories of a zip archive into memory and
+/// deserializes the tree at `path`
+///
+/// Entries with names leading outside of the archive are ignored.
+pub(crate) fn read<R, T, P>(reader: R, path: P, options: &DeserializerOptions) -> Result<T, de::Error>
+    where R: Read + Seek, T: DeserializeOwned, P: AsRef<Path> {
+    let fs = Arc::new(MemFs::new());
+    let mut archive = ZipArchive::new(reader).map_err(io::Error::from)?;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(io::Error::from)?;
+        let entry_path = match entry.enclosed_name() {
+            Some(entry_path) => entry_path,
+            None => continue,
+        };
+        if entry.is_dir() {
+            fs.create_dir(&entry_path)?;
+        } else {
+            let mut content = vec![];
+            entry.read_to_end(&mut content)?;
+            if let Some(parent) = entry_path.parent() {
+                fs.create_dir(parent)?;
+            }
+            fs.write(&entry_path, &content)?;
+        }
+    }
+    T::deserialize(FilesystemDeserializer::with_backend(path, options.clone(), fs))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Mode {
+        Plain(String),
+        Tls { cert: String },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Server {
+        host: String,
+        ports: Vec<u16>,
+        mode: Mode,
+    }
+
+    #[test]
+    fn round_trip() {
+        let server = Server { host: "localhost".into(), ports: vec![80, 443], mode: Mode::Plain("http".into()) };
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        write(&mut writer, "server", &server, &SerializerOptions::new()).unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(Cursor::new(&archive)).unwrap();
+        let names: Vec<_> = zip.file_names().map(String::from).collect();
+        assert_eq!(names, vec!["server/", "server/host", "server/mode/", "server/mode/value", "server/mode/variant",
+                               "server/ports/", "server/ports/0", "server/ports/1"]);
+        assert_eq!(zip.by_name("server/ports/1").unwrap().unix_mode(), Some(0o100644));
+
+        let read: Server = read(Cursor::new(&archive), "server", &DeserializerOptions::new()).unwrap();
+        assert_eq!(read, server);
+    }
+
+    #[test]
+    fn directory_interchange() {
+        use tempdir::TempDir;
+        let tmp = TempDir::new("serde-fs").unwrap();
+        let server = Server { host: "localhost".into(), ports: vec![], mode: Mode::Tls { cert: "cert.pem".into() } };
+        ::to_fs(tmp.path(), &server).unwrap();
+        let fs: &dyn Backend = &::StdFs::default();
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        backend::walk(fs, tmp.path(), &mut |path, content| {
+            let name = entry_name(path.strip_prefix(tmp.path()).unwrap());
+            match content {
+                Some(content) => {
+                    writer.start_file(format!("server/{}", name), SimpleFileOptions::default())?;
+                    writer.write_all(&content)
+                },
+                None => Ok(writer.add_directory(format!("server/{}", name), SimpleFileOptions::default())?),
+            }
+        }).unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+        let read: Server = read(Cursor::new(&archive), "server", &DeserializerOptions::new()).unwrap();
+        assert_eq!(read, server);
+    }
+
+}